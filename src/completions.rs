@@ -0,0 +1,364 @@
+use clap::{Arg, Command, ValueEnum};
+use std::fmt::Write;
+
+// The shells we can write completion scripts for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+// Builds a completion script for `cmd` in the given shell.
+// Subcommands, their flags and the possible values of enum options are all
+// taken from the clap definition, so the script stays in sync with the CLI.
+pub fn generate(shell: Shell, mut cmd: Command) -> String {
+    // Building fills in the generated --help/--version flags and copies
+    // global options such as --file onto every subcommand.
+    cmd.build();
+    match shell {
+        Shell::Bash => bash(&cmd),
+        // zsh can run bash completion functions through bashcompinit
+        Shell::Zsh => format!(
+            "autoload -U +X bashcompinit && bashcompinit\n{}",
+            bash(&cmd)
+        ),
+        Shell::Fish => fish(&cmd),
+        Shell::Powershell => powershell(&cmd),
+    }
+}
+
+// Every visible subcommand, with the built-in `help` left out.
+fn subcommands(cmd: &Command) -> impl Iterator<Item = &Command> {
+    cmd.get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+}
+
+// The `--long` and `-s` spellings of every flag or option on a command.
+fn flag_words(cmd: &Command) -> Vec<String> {
+    let mut words = Vec::new();
+    for arg in cmd.get_arguments().filter(|a| !a.is_positional()) {
+        if let Some(long) = arg.get_long() {
+            words.push(format!("--{}", long));
+        }
+        if let Some(short) = arg.get_short() {
+            words.push(format!("-{}", short));
+        }
+    }
+    words
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect()
+}
+
+// Whether an option expects a value after it, like `--file <PATH>`
+fn takes_value(arg: &Arg) -> bool {
+    !arg.is_positional() && arg.get_action().takes_values()
+}
+
+// The `--long` and `-s` spellings of every option on a command that takes a value.
+fn valued_words(cmd: &Command) -> Vec<String> {
+    cmd.get_arguments()
+        .filter(|a| takes_value(a))
+        .flat_map(|a| {
+            let mut spellings = Vec::new();
+            if let Some(long) = a.get_long() {
+                spellings.push(format!("--{}", long));
+            }
+            if let Some(short) = a.get_short() {
+                spellings.push(format!("-{}", short));
+            }
+            spellings
+        })
+        .collect()
+}
+
+// Each option with a fixed set of values, by its spellings (`--priority|-p`),
+// across the command and its subcommands. An option several subcommands
+// share is only listed once.
+fn valued_options(cmd: &Command) -> Vec<(Vec<String>, Vec<String>)> {
+    let mut options = Vec::new();
+    let mut seen = Vec::new();
+    for arg in std::iter::once(cmd)
+        .chain(subcommands(cmd))
+        .flat_map(|c| c.get_arguments())
+        .filter(|a| takes_value(a))
+    {
+        let values = possible_values(arg);
+        let Some(long) = arg.get_long() else { continue };
+        if values.is_empty() || seen.contains(&long) {
+            continue;
+        }
+        seen.push(long);
+        let mut spellings = vec![format!("--{}", long)];
+        if let Some(short) = arg.get_short() {
+            spellings.push(format!("-{}", short));
+        }
+        options.push((spellings, values));
+    }
+    options
+}
+
+// The words to offer after each subcommand: its flags, and the values of
+// positional enum arguments such as the format of `export`.
+fn subcommand_words(sub: &Command) -> Vec<String> {
+    let mut words = flag_words(sub);
+    for arg in sub.get_arguments().filter(|a| a.is_positional()) {
+        words.extend(possible_values(arg));
+    }
+    words
+}
+
+fn bash(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let func = format!("_{}", name.replace('-', "_"));
+    let mut out = String::new();
+
+    // Options that take a value, so the word after them isn't mistaken for a subcommand
+    let valued = valued_words(cmd);
+
+    let names: Vec<&str> = subcommands(cmd).map(|s| s.get_name()).collect();
+
+    writeln!(out, "{}() {{", func).unwrap();
+    writeln!(out, "    local cur prev subcommand i").unwrap();
+    writeln!(out, "    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"").unwrap();
+    writeln!(out, "    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"").unwrap();
+    writeln!(out, "    subcommand=\"\"").unwrap();
+    writeln!(out, "    for ((i = 1; i < COMP_CWORD; i++)); do").unwrap();
+    writeln!(out, "        case \"${{COMP_WORDS[i]}}\" in").unwrap();
+    if !valued.is_empty() {
+        writeln!(out, "            {}) ((i++)) ;;", valued.join("|")).unwrap();
+    }
+    writeln!(out, "            -*) ;;").unwrap();
+    writeln!(
+        out,
+        "            *) subcommand=\"${{COMP_WORDS[i]}}\"; break ;;"
+    )
+    .unwrap();
+    writeln!(out, "        esac").unwrap();
+    writeln!(out, "    done").unwrap();
+    writeln!(out).unwrap();
+
+    // Complete the values of enum options such as `--priority <low|medium|high>`
+    writeln!(out, "    case \"$prev\" in").unwrap();
+    for (spellings, values) in valued_options(cmd) {
+        writeln!(
+            out,
+            "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;",
+            spellings.join("|"),
+            values.join(" ")
+        )
+        .unwrap();
+    }
+    writeln!(out, "    esac").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "    local words").unwrap();
+    writeln!(out, "    case \"$subcommand\" in").unwrap();
+    let top_level: Vec<String> = names
+        .iter()
+        .map(|n| n.to_string())
+        .chain(flag_words(cmd))
+        .collect();
+    writeln!(out, "        \"\") words=\"{}\" ;;", top_level.join(" ")).unwrap();
+    for sub in subcommands(cmd) {
+        writeln!(
+            out,
+            "        {}) words=\"{}\" ;;",
+            sub.get_name(),
+            subcommand_words(sub).join(" ")
+        )
+        .unwrap();
+    }
+    writeln!(out, "    esac").unwrap();
+    writeln!(out, "    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out, "complete -o default -F {} {}", func, name).unwrap();
+    out
+}
+
+// Escapes text for use inside a single-quoted fish string.
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish_arg_line(out: &mut String, name: &str, condition: &str, arg: &Arg) {
+    let mut line = format!("complete -c {} -n {}", name, fish_quote(condition));
+    if let Some(long) = arg.get_long() {
+        line.push_str(&format!(" -l {}", long));
+    }
+    if let Some(short) = arg.get_short() {
+        line.push_str(&format!(" -s {}", short));
+    }
+    if takes_value(arg) {
+        line.push_str(" -r");
+        let values = possible_values(arg);
+        if !values.is_empty() {
+            line.push_str(&format!(" -f -a {}", fish_quote(&values.join(" "))));
+        }
+    }
+    if let Some(help) = arg.get_help() {
+        line.push_str(&format!(" -d {}", fish_quote(&help.to_string())));
+    }
+    writeln!(out, "{}", line).unwrap();
+}
+
+fn fish(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let mut out = String::new();
+
+    for arg in cmd.get_arguments().filter(|a| !a.is_positional()) {
+        fish_arg_line(&mut out, name, "true", arg);
+    }
+
+    for sub in subcommands(cmd) {
+        let mut line = format!(
+            "complete -c {} -f -n '__fish_use_subcommand' -a {}",
+            name,
+            sub.get_name()
+        );
+        if let Some(about) = sub.get_about() {
+            line.push_str(&format!(" -d {}", fish_quote(&about.to_string())));
+        }
+        writeln!(out, "{}", line).unwrap();
+
+        let condition = format!("__fish_seen_subcommand_from {}", sub.get_name());
+        for arg in sub.get_arguments() {
+            if arg.is_positional() {
+                let values = possible_values(arg);
+                if !values.is_empty() {
+                    writeln!(
+                        out,
+                        "complete -c {} -f -n {} -a {}",
+                        name,
+                        fish_quote(&condition),
+                        fish_quote(&values.join(" "))
+                    )
+                    .unwrap();
+                }
+            } else if !arg.is_global_set() {
+                fish_arg_line(&mut out, name, &condition, arg);
+            }
+        }
+    }
+    out
+}
+
+// Quotes text as a single-quoted PowerShell string.
+fn powershell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+// The same approach as the bash script: find the subcommand, skipping the
+// values of options like --file, then offer the values of an enum option
+// just typed, or else the subcommand's flags.
+fn powershell(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let quote_all = |words: &[String]| -> String {
+        let quoted: Vec<String> = words.iter().map(|w| powershell_quote(w)).collect();
+        format!("@({})", quoted.join(", "))
+    };
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "Register-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{",
+        powershell_quote(name)
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "    param($wordToComplete, $commandAst, $cursorPosition)"
+    )
+    .unwrap();
+    // The words before the one being completed, without the program name
+    writeln!(
+        out,
+        "    $before = @($commandAst.CommandElements | Select-Object -Skip 1 | ForEach-Object {{ $_.ToString() }})"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "    if ($wordToComplete -ne '') {{ $before = @($before | Select-Object -SkipLast 1) }}"
+    )
+    .unwrap();
+    writeln!(out, "    $valued = {}", quote_all(&valued_words(cmd))).unwrap();
+    writeln!(out, "    $subcommand = ''").unwrap();
+    writeln!(out, "    for ($i = 0; $i -lt $before.Count; $i++) {{").unwrap();
+    writeln!(
+        out,
+        "        if ($valued -ccontains $before[$i]) {{ $i++ }}"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "        elseif (-not $before[$i].StartsWith('-')) {{ $subcommand = $before[$i]; break }}"
+    )
+    .unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(
+        out,
+        "    $prev = if ($before.Count -gt 0) {{ $before[-1] }} else {{ '' }}"
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+
+    // Values of enum options such as `--priority <low|medium|high>`
+    writeln!(out, "    $words = $null").unwrap();
+    writeln!(out, "    switch -CaseSensitive ($prev) {{").unwrap();
+    for (spellings, values) in valued_options(cmd) {
+        for spelling in spellings {
+            writeln!(
+                out,
+                "        {} {{ $words = {} }}",
+                powershell_quote(&spelling),
+                quote_all(&values)
+            )
+            .unwrap();
+        }
+    }
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "    if ($null -eq $words) {{").unwrap();
+    writeln!(out, "        switch -CaseSensitive ($subcommand) {{").unwrap();
+    let top_level: Vec<String> = subcommands(cmd)
+        .map(|s| s.get_name().to_string())
+        .chain(flag_words(cmd))
+        .collect();
+    writeln!(
+        out,
+        "            '' {{ $words = {} }}",
+        quote_all(&top_level)
+    )
+    .unwrap();
+    for sub in subcommands(cmd) {
+        writeln!(
+            out,
+            "            {} {{ $words = {} }}",
+            powershell_quote(sub.get_name()),
+            quote_all(&subcommand_words(sub))
+        )
+        .unwrap();
+    }
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "    $words | Where-Object {{ $_ -clike \"$wordToComplete*\" }} | ForEach-Object {{"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)"
+    )
+    .unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
@@ -0,0 +1,366 @@
+use anyhow::{Context, Result, anyhow, bail};
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+
+use crate::SortKey;
+
+// Defaults read from the config file. Every setting is optional, and a
+// command-line flag always wins over the value given here.
+#[derive(Debug, Default)]
+pub struct Config {
+    // The to-do file to use when neither --file nor TODO_FILE is given
+    pub file: Option<PathBuf>,
+    // The order `list` uses when --sort isn't given
+    pub default_sort: Option<SortKey>,
+    // Set to false to never color the output
+    pub color: Option<bool>,
+    // How `list` marks completed and pending items, and shows due dates
+    pub done_marker: Option<String>,
+    pub pending_marker: Option<String>,
+    pub date_format: Option<String>,
+    // Set to true to always save as if --sync was given
+    pub sync: Option<bool>,
+    // The longest task description `add` and `edit` accept, in characters
+    pub max_task_length: Option<usize>,
+    // Set to true to always print plain text as if --no-emoji was given
+    pub no_emoji: Option<bool>,
+    // The lowest ID handed to new to-dos, so lists kept on different machines
+    // can use different ranges (e.g. 1000 and 2000) and be merged later
+    pub id_start: Option<u32>,
+}
+
+// Where the config file lives: $XDG_CONFIG_HOME/todo/config.toml, falling
+// back to ~/.config/todo/config.toml.
+fn config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("todo").join("config.toml"))
+}
+
+// Reads the config file. A missing file just means every setting is left at
+// its built-in default; a file that can't be understood is an error, so a typo
+// doesn't quietly change which list you're working on.
+pub fn load() -> Result<Config> {
+    let Some(path) = config_path() else {
+        return Ok(Config::default());
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => parse(&contents, &path)
+            .with_context(|| format!("Invalid config file {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+// A value on the right-hand side of `key = value`.
+enum Value {
+    String(String),
+    Bool(bool),
+    Integer(i64),
+}
+
+// Parses the small subset of TOML the config file needs: `key = value` lines
+// holding quoted strings, booleans or whole numbers, with `#` comments.
+fn parse(contents: &str, path: &Path) -> Result<Config> {
+    let mut config = Config::default();
+    for (index, line) in contents.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {}: expected `key = value`, got `{}`", number, line);
+        };
+        let key = key.trim();
+        let value = parse_value(value.trim()).map_err(|e| anyhow!("line {}: {}", number, e))?;
+
+        match (key, value) {
+            ("file", Value::String(file)) => config.file = Some(PathBuf::from(file)),
+            ("default_sort", Value::String(name)) => {
+                let key = SortKey::from_str(&name, true).map_err(|_| {
+                    anyhow!(
+                        "line {}: unknown default_sort '{}', expected id, priority or due",
+                        number,
+                        name
+                    )
+                })?;
+                config.default_sort = Some(key);
+            }
+            ("color", Value::Bool(color)) => config.color = Some(color),
+            ("done_marker", Value::String(marker)) => config.done_marker = Some(marker),
+            ("pending_marker", Value::String(marker)) => config.pending_marker = Some(marker),
+            ("date_format", Value::String(format)) => config.date_format = Some(format),
+            ("sync", Value::Bool(sync)) => config.sync = Some(sync),
+            ("no_emoji", Value::Bool(no_emoji)) => config.no_emoji = Some(no_emoji),
+            ("max_task_length", Value::Integer(length)) if length > 0 => {
+                config.max_task_length = Some(length as usize);
+            }
+            ("id_start", Value::Integer(start)) if start > 0 && start <= i64::from(u32::MAX) => {
+                config.id_start = Some(start as u32);
+            }
+            ("id_start", _) => {
+                bail!("line {}: id_start must be a positive whole number", number)
+            }
+            ("max_task_length", _) => {
+                bail!(
+                    "line {}: max_task_length must be a positive whole number",
+                    number
+                )
+            }
+            ("file" | "default_sort" | "done_marker" | "pending_marker" | "date_format", _) => {
+                bail!("line {}: {} must be a quoted string", number, key)
+            }
+            ("color" | "sync" | "no_emoji", _) => {
+                bail!("line {}: {} must be true or false", number, key)
+            }
+            _ => eprintln!(
+                "Warning: ignoring unknown setting '{}' in {}",
+                key,
+                path.display()
+            ),
+        }
+    }
+    Ok(config)
+}
+
+// Parses a quoted string ("..." with backslash escapes, or '...' taken
+// literally), a bare true/false or a whole number, allowing a trailing comment.
+fn parse_value(text: &str) -> Result<Value> {
+    let (value, rest) = if let Some(literal) = text.strip_prefix('\'') {
+        let end = literal
+            .find('\'')
+            .ok_or_else(|| anyhow!("missing closing ' in {}", text))?;
+        (
+            Value::String(literal[..end].to_string()),
+            &literal[end + 1..],
+        )
+    } else if let Some(quoted) = text.strip_prefix('"') {
+        let mut string = String::new();
+        let mut chars = quoted.char_indices();
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    end = Some(i);
+                    break;
+                }
+                '\\' => match chars.next() {
+                    Some((_, '"')) => string.push('"'),
+                    Some((_, '\\')) => string.push('\\'),
+                    Some((_, 'n')) => string.push('\n'),
+                    Some((_, 't')) => string.push('\t'),
+                    Some((_, other)) => bail!("unsupported escape \\{} in {}", other, text),
+                    None => bail!("missing closing \" in {}", text),
+                },
+                _ => string.push(c),
+            }
+        }
+        let end = end.ok_or_else(|| anyhow!("missing closing \" in {}", text))?;
+        (Value::String(string), &quoted[end + 1..])
+    } else {
+        let bare = text.split('#').next().unwrap_or_default().trim();
+        let value = match bare {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => match bare.replace('_', "").parse() {
+                Ok(number) => Value::Integer(number),
+                Err(_) => bail!(
+                    "expected a quoted string, true, false or a number, got `{}`",
+                    bare
+                ),
+            },
+        };
+        (value, "")
+    };
+
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        bail!("unexpected `{}` after the value", rest);
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(contents: &str) -> Config {
+        parse(contents, Path::new("config.toml")).unwrap()
+    }
+
+    // The full message, including the context added by `anyhow`
+    fn error(contents: &str) -> String {
+        format!(
+            "{:#}",
+            parse(contents, Path::new("config.toml")).unwrap_err()
+        )
+    }
+
+    #[test]
+    fn reads_every_setting() {
+        let config = config(
+            r#"
+            # Comments and blank lines are skipped
+
+            file = "~/todos.json"
+            default_sort = "Priority"
+            color = false
+            done_marker = '[x]'
+            pending_marker = "[ ]"
+            date_format = "%d/%m"
+            sync = true
+            no_emoji = true
+            max_task_length = 1_000
+            id_start = 2000
+            "#,
+        );
+        assert_eq!(config.file, Some(PathBuf::from("~/todos.json")));
+        assert_eq!(config.default_sort, Some(SortKey::Priority));
+        assert_eq!(config.color, Some(false));
+        assert_eq!(config.done_marker.as_deref(), Some("[x]"));
+        assert_eq!(config.pending_marker.as_deref(), Some("[ ]"));
+        assert_eq!(config.date_format.as_deref(), Some("%d/%m"));
+        assert_eq!(config.sync, Some(true));
+        assert_eq!(config.no_emoji, Some(true));
+        assert_eq!(config.max_task_length, Some(1000));
+        assert_eq!(config.id_start, Some(2000));
+    }
+
+    #[test]
+    fn empty_file_leaves_the_defaults() {
+        let config = config("");
+        assert!(config.file.is_none());
+        assert!(config.default_sort.is_none());
+        assert!(config.color.is_none());
+    }
+
+    #[test]
+    fn unknown_settings_are_ignored() {
+        let config = config("colour = true\ncolor = true");
+        assert_eq!(config.color, Some(true));
+    }
+
+    #[test]
+    fn strings_take_escapes_and_trailing_comments() {
+        let marker = |line: &str| config(line).done_marker.unwrap();
+        assert_eq!(marker(r#"done_marker = "a\"b\\c\nd\te""#), "a\"b\\c\nd\te");
+        assert_eq!(marker(r#"done_marker = "x # y" # a comment"#), "x # y");
+        assert_eq!(marker(r"done_marker = 'C:\todo\' # literal"), r"C:\todo\");
+        assert_eq!(marker("done_marker=\"\""), "");
+        assert_eq!(config("sync = true # always").sync, Some(true));
+        assert_eq!(config("id_start = 5 # five").id_start, Some(5));
+    }
+
+    #[test]
+    fn lines_need_an_equals_sign() {
+        assert_eq!(
+            error("\ncolor"),
+            "line 2: expected `key = value`, got `color`"
+        );
+    }
+
+    #[test]
+    fn quoted_strings_must_be_closed() {
+        assert_eq!(
+            error("file = 'todos.json"),
+            "line 1: missing closing ' in 'todos.json"
+        );
+        assert_eq!(
+            error(r#"file = "todos.json"#),
+            r#"line 1: missing closing " in "todos.json"#
+        );
+        assert_eq!(
+            error(r#"file = "todos\"#),
+            r#"line 1: missing closing " in "todos\"#
+        );
+    }
+
+    #[test]
+    fn unsupported_escapes_are_errors() {
+        assert_eq!(
+            error(r#"file = "C:\data""#),
+            r#"line 1: unsupported escape \d in "C:\data""#
+        );
+        assert_eq!(
+            error(r#"file = "a\qb""#),
+            r#"line 1: unsupported escape \q in "a\qb""#
+        );
+    }
+
+    #[test]
+    fn bare_values_must_be_booleans_or_numbers() {
+        assert_eq!(
+            error("file = todos.json"),
+            "line 1: expected a quoted string, true, false or a number, got `todos.json`"
+        );
+        assert_eq!(
+            error("sync = yes # please"),
+            "line 1: expected a quoted string, true, false or a number, got `yes`"
+        );
+    }
+
+    #[test]
+    fn nothing_may_follow_a_value_but_a_comment() {
+        assert_eq!(
+            error(r#"file = "a" "b""#),
+            "line 1: unexpected `\"b\"` after the value"
+        );
+        assert_eq!(
+            error("file = 'a' b"),
+            "line 1: unexpected `b` after the value"
+        );
+    }
+
+    #[test]
+    fn default_sort_must_be_a_sort_key() {
+        assert_eq!(
+            error("default_sort = \"size\""),
+            "line 1: unknown default_sort 'size', expected id, priority or due"
+        );
+    }
+
+    #[test]
+    fn numbers_must_be_positive_and_in_range() {
+        for text in ["0", "-3", "\"10\"", "true"] {
+            assert_eq!(
+                error(&format!("id_start = {}", text)),
+                "line 1: id_start must be a positive whole number"
+            );
+            assert_eq!(
+                error(&format!("max_task_length = {}", text)),
+                "line 1: max_task_length must be a positive whole number"
+            );
+        }
+        // IDs are stored as u32
+        assert_eq!(config("id_start = 4294967295").id_start, Some(u32::MAX));
+        assert_eq!(
+            error("id_start = 4294967296"),
+            "line 1: id_start must be a positive whole number"
+        );
+    }
+
+    #[test]
+    fn settings_must_have_the_right_type() {
+        for key in [
+            "file",
+            "default_sort",
+            "done_marker",
+            "pending_marker",
+            "date_format",
+        ] {
+            assert_eq!(
+                error(&format!("{} = 3", key)),
+                format!("line 1: {} must be a quoted string", key)
+            );
+        }
+        for key in ["color", "sync", "no_emoji"] {
+            assert_eq!(
+                error(&format!("{} = \"true\"", key)),
+                format!("line 1: {} must be true or false", key)
+            );
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A plain calendar date, stored in files as "YYYY-MM-DD".
+// The field order matters: the derived Ord compares year, then month, then day,
+// so dates sort chronologically.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct Date {
+    year: i32,
+    month: u32,
+    day: u32,
+}
+
+impl Date {
+    // Builds a date, checking that the day actually exists in that month.
+    pub fn new(year: i32, month: u32, day: u32) -> Result<Date> {
+        if !(1..=12).contains(&month) {
+            bail!("month must be between 1 and 12, got {}", month);
+        }
+        let max_day = days_in_month(year, month);
+        if day == 0 || day > max_day {
+            bail!(
+                "day must be between 1 and {} for that month, got {}",
+                max_day,
+                day
+            );
+        }
+        Ok(Date { year, month, day })
+    }
+
+    // Today's date where the user is, in their local time zone, so a to-do
+    // due today isn't overdue yet on a late evening west of UTC. Falls back
+    // to the date in UTC where the local time zone can't be found out.
+    pub fn today() -> Date {
+        Timestamp::now().local_date()
+    }
+
+    // The date `days` days later (or earlier, if negative).
+    pub fn add_days(self, days: i64) -> Date {
+        Date::from_days(self.to_days() + days)
+    }
+
+    // The same day `months` months later. Days that don't exist in the target
+    // month are clamped to its last day, so Jan 31 + 1 month is Feb 28 (or 29).
+    pub fn add_months(self, months: u32) -> Date {
+        let total = self.year as i64 * 12 + (self.month as i64 - 1) + months as i64;
+        let year = total.div_euclid(12) as i32;
+        let month = total.rem_euclid(12) as u32 + 1;
+        let day = self.day.min(days_in_month(year, month));
+        Date { year, month, day }
+    }
+
+    // Day of the week, counting Monday as 0 and Sunday as 6.
+    fn weekday(self) -> i64 {
+        // 1970-01-01 was a Thursday
+        (self.to_days() + 3).rem_euclid(7)
+    }
+
+    // Formats the date with a strftime-style pattern. Supported are %Y (2025),
+    // %y (25), %m (04), %d (05), %e (5), %b (Apr), %B (April), %a (Sat),
+    // %A (Saturday) and %% for a literal %. Anything else is copied as is.
+    pub fn format(self, pattern: &str) -> String {
+        const MONTHS: [&str; 12] = [
+            "January",
+            "February",
+            "March",
+            "April",
+            "May",
+            "June",
+            "July",
+            "August",
+            "September",
+            "October",
+            "November",
+            "December",
+        ];
+        const WEEKDAYS: [&str; 7] = [
+            "Monday",
+            "Tuesday",
+            "Wednesday",
+            "Thursday",
+            "Friday",
+            "Saturday",
+            "Sunday",
+        ];
+        let month = MONTHS[self.month as usize - 1];
+        let weekday = WEEKDAYS[self.weekday() as usize];
+
+        let mut out = String::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => out.push_str(&self.year.to_string()),
+                Some('y') => out.push_str(&format!("{:02}", self.year.rem_euclid(100))),
+                Some('m') => out.push_str(&format!("{:02}", self.month)),
+                Some('d') => out.push_str(&format!("{:02}", self.day)),
+                Some('e') => out.push_str(&self.day.to_string()),
+                Some('b') => out.push_str(&month[..3]),
+                Some('B') => out.push_str(month),
+                Some('a') => out.push_str(&weekday[..3]),
+                Some('A') => out.push_str(weekday),
+                Some('%') => out.push('%'),
+                Some(other) => {
+                    out.push('%');
+                    out.push(other);
+                }
+                None => out.push('%'),
+            }
+        }
+        out
+    }
+
+    // The Sunday that ends this date's week (the date itself if it is a Sunday).
+    pub fn end_of_week(self) -> Date {
+        self.add_days(6 - self.weekday())
+    }
+
+    // Parses a due date as people tend to type it, relative to `today`:
+    // "today", "tomorrow", "yesterday", a weekday name such as "friday" or
+    // "next fri" (the next such day after today), a span such as "in 3 days"
+    // or "+2w", or an exact YYYY-MM-DD date.
+    pub fn parse_relative(s: &str, today: Date) -> Result<Date> {
+        let text = s.trim().to_lowercase();
+        match text.as_str() {
+            "today" => return Ok(today),
+            "tomorrow" => return Ok(today.add_days(1)),
+            "yesterday" => return Ok(today.add_days(-1)),
+            _ => {}
+        }
+
+        let weekday_name = text.strip_prefix("next ").unwrap_or(&text);
+        if let Some(target) = parse_weekday(weekday_name) {
+            let ahead = (target - today.weekday()).rem_euclid(7);
+            // Never today: "friday" on a Friday means a week from now
+            let ahead = if ahead == 0 { 7 } else { ahead };
+            return Ok(today.add_days(ahead));
+        }
+
+        if let Ok(span) = text.parse::<Span>() {
+            return Ok(span.after(today));
+        }
+
+        // Something shaped like YYYY-MM-DD gets the precise reason it's invalid
+        if text.contains('-') {
+            return text.parse();
+        }
+        text.parse().map_err(|_| {
+            anyhow!(
+                "could not understand the date '{}'; try YYYY-MM-DD, today, tomorrow, a weekday such as friday, or a span such as 'in 3 days' or +2w",
+                s
+            )
+        })
+    }
+
+    // Converts a count of days since 1970-01-01 into a date.
+    // This is Howard Hinnant's `civil_from_days` algorithm.
+    fn from_days(days: i64) -> Date {
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+        Date { year, month, day }
+    }
+
+    // Converts the date into a count of days since 1970-01-01.
+    // This is the inverse of `from_days` (Hinnant's `days_from_civil`).
+    fn to_days(self) -> i64 {
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + i64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Monday is 0, matching `Date::weekday`.
+fn parse_weekday(name: &str) -> Option<i64> {
+    let day = match name {
+        "monday" | "mon" => 0,
+        "tuesday" | "tue" | "tues" => 1,
+        "wednesday" | "wed" => 2,
+        "thursday" | "thu" | "thurs" => 3,
+        "friday" | "fri" => 4,
+        "saturday" | "sat" => 5,
+        "sunday" | "sun" => 6,
+        _ => return None,
+    };
+    Some(day)
+}
+
+// std has no time zone support, so the local date comes from the C library.
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_char, c_int, c_long};
+
+    // struct tm, as laid out by glibc, musl and the BSDs (including macOS)
+    #[repr(C)]
+    pub struct Tm {
+        pub tm_sec: c_int,
+        pub tm_min: c_int,
+        pub tm_hour: c_int,
+        pub tm_mday: c_int,
+        pub tm_mon: c_int,
+        pub tm_year: c_int,
+        pub tm_wday: c_int,
+        pub tm_yday: c_int,
+        pub tm_isdst: c_int,
+        pub tm_gmtoff: c_long,
+        pub tm_zone: *const c_char,
+    }
+
+    unsafe extern "C" {
+        pub fn tzset();
+        // time_t is a long on every Unix this builds for
+        pub fn localtime_r(time: *const c_long, result: *mut Tm) -> *mut Tm;
+    }
+}
+
+// The date `secs` seconds after the epoch falls on in the local time zone
+// ($TZ, or the system's), if it can be worked out.
+#[cfg(unix)]
+fn local_date(secs: i64) -> Option<Date> {
+    let secs = std::ffi::c_long::try_from(secs).ok()?;
+    let mut tm = std::mem::MaybeUninit::<sys::Tm>::uninit();
+    // SAFETY: localtime_r only writes to the struct it's given; tzset makes
+    // sure it sees the current $TZ
+    let tm = unsafe {
+        sys::tzset();
+        if sys::localtime_r(&secs, tm.as_mut_ptr()).is_null() {
+            return None;
+        }
+        tm.assume_init()
+    };
+    let month = u32::try_from(tm.tm_mon + 1).ok()?;
+    let day = u32::try_from(tm.tm_mday).ok()?;
+    Date::new(tm.tm_year + 1900, month, day).ok()
+}
+
+#[cfg(not(unix))]
+fn local_date(_secs: i64) -> Option<Date> {
+    None
+}
+
+// A length of time such as "3d", "+2w", "1m" or "in 3 days".
+// Months are kept separate from days because they vary in length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Span {
+    Days(i64),
+    Months(u32),
+}
+
+impl Span {
+    // The date this span after `date`.
+    pub fn after(self, date: Date) -> Date {
+        match self {
+            Span::Days(days) => date.add_days(days),
+            Span::Months(months) => date.add_months(months),
+        }
+    }
+}
+
+impl FromStr for Span {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Span> {
+        let invalid = || {
+            anyhow!(
+                "invalid duration '{}', expected something like 3d, 2w or 1m",
+                s
+            )
+        };
+
+        let text = s.trim().to_lowercase();
+        let text = text.strip_prefix("in ").unwrap_or(&text);
+        let text = text.strip_prefix('+').unwrap_or(text).trim();
+
+        // Split "3days" or "3 days" into the number and the unit
+        let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return Err(invalid());
+        }
+        let count: u32 = text[..digits].parse().map_err(|_| invalid())?;
+        let unit = text[digits..].trim();
+
+        let span = match unit {
+            "d" | "day" | "days" => Span::Days(i64::from(count)),
+            "w" | "week" | "weeks" => Span::Days(i64::from(count) * 7),
+            "m" | "month" | "months" => Span::Months(count),
+            "y" | "year" | "years" => Span::Months(count.checked_mul(12).ok_or_else(invalid)?),
+            _ => return Err(invalid()),
+        };
+        Ok(span)
+    }
+}
+
+impl FromStr for Date {
+    type Err = anyhow::Error;
+
+    // Parses an ISO 8601 date such as "2025-04-15".
+    fn from_str(s: &str) -> Result<Date> {
+        let parts: Vec<&str> = s.trim().split('-').collect();
+        let [year, month, day] = parts.as_slice() else {
+            bail!("invalid date '{}', expected YYYY-MM-DD", s);
+        };
+        let year = year
+            .parse()
+            .with_context(|| format!("invalid year in date '{}'", s))?;
+        let month = month
+            .parse()
+            .with_context(|| format!("invalid month in date '{}'", s))?;
+        let day = day
+            .parse()
+            .with_context(|| format!("invalid day in date '{}'", s))?;
+        Date::new(year, month, day).map_err(|e| anyhow!("invalid date '{}': {}", s, e))
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl TryFrom<String> for Date {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Date> {
+        s.parse()
+    }
+}
+
+impl From<Date> for String {
+    fn from(date: Date) -> String {
+        date.to_string()
+    }
+}
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// A moment in time, kept as whole seconds since the Unix epoch and stored in
+// files as an RFC 3339 UTC timestamp such as "2025-04-15T09:30:00Z".
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct Timestamp {
+    secs: i64,
+}
+
+impl Timestamp {
+    pub fn now() -> Timestamp {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        Timestamp { secs }
+    }
+
+    // The same time of day, `days` whole days earlier.
+    pub fn days_before(self, days: u32) -> Timestamp {
+        Timestamp {
+            secs: self.secs - i64::from(days) * SECONDS_PER_DAY,
+        }
+    }
+
+    // The calendar day (in UTC) this moment falls on.
+    pub fn date(self) -> Date {
+        Date::from_days(self.secs.div_euclid(SECONDS_PER_DAY))
+    }
+
+    // The calendar day this moment falls on where the user is, the same way
+    // Date::today() works it out (falling back to the day in UTC), so an item
+    // created late in the evening west of UTC belongs to that evening's date.
+    pub fn local_date(self) -> Date {
+        local_date(self.secs).unwrap_or_else(|| self.date())
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = anyhow::Error;
+
+    // Parses the "YYYY-MM-DDTHH:MM:SSZ" form that `Display` writes.
+    fn from_str(s: &str) -> Result<Timestamp> {
+        let invalid = || anyhow!("invalid timestamp '{}', expected YYYY-MM-DDTHH:MM:SSZ", s);
+
+        let (date, time) = s.trim().split_once('T').ok_or_else(invalid)?;
+        let time = time.strip_suffix('Z').ok_or_else(invalid)?;
+        let date: Date = date.parse()?;
+
+        let parts: Vec<&str> = time.split(':').collect();
+        let [hours, minutes, seconds] = parts.as_slice() else {
+            return Err(invalid());
+        };
+        let hours: i64 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i64 = minutes.parse().map_err(|_| invalid())?;
+        let seconds: i64 = seconds.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return Err(invalid());
+        }
+
+        let secs = date.to_days() * SECONDS_PER_DAY + hours * 3600 + minutes * 60 + seconds;
+        Ok(Timestamp { secs })
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let date = self.date();
+        let time = self.secs.rem_euclid(SECONDS_PER_DAY);
+        write!(
+            f,
+            "{}T{:02}:{:02}:{:02}Z",
+            date,
+            time / 3600,
+            time % 3600 / 60,
+            time % 60
+        )
+    }
+}
+
+impl TryFrom<String> for Timestamp {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Timestamp> {
+        s.parse()
+    }
+}
+
+impl From<Timestamp> for String {
+    fn from(timestamp: Timestamp) -> String {
+        timestamp.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> Date {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn new_checks_the_day_exists() {
+        assert!(Date::new(2024, 2, 29).is_ok());
+        assert!(Date::new(2025, 2, 29).is_err());
+        assert!(Date::new(2000, 2, 29).is_ok());
+        assert!(Date::new(1900, 2, 29).is_err());
+        assert!(Date::new(2025, 4, 31).is_err());
+        assert!(Date::new(2025, 13, 1).is_err());
+        assert!(Date::new(2025, 1, 0).is_err());
+    }
+
+    #[test]
+    fn parses_and_prints_iso_dates() {
+        assert_eq!(date("2025-04-05").to_string(), "2025-04-05");
+        assert_eq!(date(" 2025-4-5 ").to_string(), "2025-04-05");
+        assert!("2025-04".parse::<Date>().is_err());
+        assert!("2025-xx-01".parse::<Date>().is_err());
+        assert!("2025-02-30".parse::<Date>().is_err());
+    }
+
+    #[test]
+    fn day_counts_round_trip() {
+        assert_eq!(date("1970-01-01").to_days(), 0);
+        assert_eq!(Date::from_days(0), date("1970-01-01"));
+        assert_eq!(Date::from_days(-1), date("1969-12-31"));
+        for day in [-800_000, -1, 0, 59, 60, 11_016, 19_782, 2_932_896] {
+            assert_eq!(Date::from_days(day).to_days(), day);
+        }
+    }
+
+    #[test]
+    fn add_days_crosses_leap_days_and_years() {
+        assert_eq!(date("2024-02-28").add_days(1), date("2024-02-29"));
+        assert_eq!(date("2024-02-28").add_days(2), date("2024-03-01"));
+        assert_eq!(date("2025-02-28").add_days(1), date("2025-03-01"));
+        assert_eq!(date("2024-12-31").add_days(1), date("2025-01-01"));
+        assert_eq!(date("2025-01-01").add_days(-1), date("2024-12-31"));
+    }
+
+    #[test]
+    fn add_months_clamps_to_the_end_of_the_month() {
+        assert_eq!(date("2025-01-31").add_months(1), date("2025-02-28"));
+        assert_eq!(date("2024-01-31").add_months(1), date("2024-02-29"));
+        assert_eq!(date("2025-03-31").add_months(1), date("2025-04-30"));
+        assert_eq!(date("2025-11-15").add_months(2), date("2026-01-15"));
+        assert_eq!(date("2024-02-29").add_months(12), date("2025-02-28"));
+    }
+
+    #[test]
+    fn weekdays_and_end_of_week() {
+        // 2025-04-14 was a Monday
+        assert_eq!(date("2025-04-14").weekday(), 0);
+        assert_eq!(date("2025-04-20").weekday(), 6);
+        assert_eq!(date("1970-01-01").weekday(), 3);
+        assert_eq!(date("2025-04-14").end_of_week(), date("2025-04-20"));
+        assert_eq!(date("2025-04-20").end_of_week(), date("2025-04-20"));
+        assert_eq!(date("2025-12-29").end_of_week(), date("2026-01-04"));
+    }
+
+    #[test]
+    fn parse_relative_understands_words_weekdays_and_spans() {
+        // A Friday
+        let today = date("2025-04-18");
+        let parse = |s| Date::parse_relative(s, today).unwrap();
+        assert_eq!(parse("today"), today);
+        assert_eq!(parse("Tomorrow"), date("2025-04-19"));
+        assert_eq!(parse("yesterday"), date("2025-04-17"));
+        // The same weekday means a week from now, and weeks wrap around
+        assert_eq!(parse("friday"), date("2025-04-25"));
+        assert_eq!(parse("next fri"), date("2025-04-25"));
+        assert_eq!(parse("mon"), date("2025-04-21"));
+        assert_eq!(parse("thursday"), date("2025-04-24"));
+        assert_eq!(parse("in 3 days"), date("2025-04-21"));
+        assert_eq!(parse("+2w"), date("2025-05-02"));
+        assert_eq!(parse("2025-12-25"), date("2025-12-25"));
+        assert!(Date::parse_relative("someday", today).is_err());
+        assert!(Date::parse_relative("2025-02-30", today).is_err());
+    }
+
+    #[test]
+    fn spans() {
+        assert_eq!("3d".parse::<Span>().unwrap(), Span::Days(3));
+        assert_eq!("+2w".parse::<Span>().unwrap(), Span::Days(14));
+        assert_eq!("in 1 month".parse::<Span>().unwrap(), Span::Months(1));
+        assert_eq!("2Y".parse::<Span>().unwrap(), Span::Months(24));
+        assert_eq!("0d".parse::<Span>().unwrap(), Span::Days(0));
+        for invalid in ["", "d", "3", "3x", "-3d", "99999999999d"] {
+            assert!(invalid.parse::<Span>().is_err(), "{:?}", invalid);
+        }
+        // Too many months to count, rather than overflowing
+        assert!("999999999y".parse::<Span>().is_err());
+        assert!("4294967295m".parse::<Span>().is_ok());
+    }
+
+    #[test]
+    fn format_patterns() {
+        let day = date("2025-04-05");
+        assert_eq!(day.format("%d %b %Y"), "05 Apr 2025");
+        assert_eq!(day.format("%A %e %B %y"), "Saturday 5 April 25");
+        assert_eq!(day.format("%a %m/%d"), "Sat 04/05");
+        assert_eq!(day.format("100%% %q %"), "100% %q %");
+    }
+
+    #[test]
+    fn timestamps_round_trip() {
+        let at: Timestamp = "2024-02-29T23:59:59Z".parse().unwrap();
+        assert_eq!(at.to_string(), "2024-02-29T23:59:59Z");
+        assert_eq!(at.date(), date("2024-02-29"));
+        assert_eq!(at.days_before(1).to_string(), "2024-02-28T23:59:59Z");
+        assert!("2024-02-29T24:00:00Z".parse::<Timestamp>().is_err());
+        assert!("2024-02-29 10:00:00".parse::<Timestamp>().is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+
+use crate::date::{Date, Timestamp};
+use crate::{Todo, TodoList, parse_tag};
+
+// One thing `doctor` found wrong with the list, and what to do about it.
+pub struct Problem {
+    pub message: String,
+    pub suggestion: String,
+    // Whether `doctor --fix` takes care of it (see repair)
+    pub fixable: bool,
+}
+
+impl Problem {
+    fn new(message: String, suggestion: String, fixable: bool) -> Problem {
+        Problem {
+            message,
+            suggestion,
+            fixable,
+        }
+    }
+}
+
+// The tags written the way `add --tag` would have stored them: lowercase,
+// without a leading '#', and each one once. Tags that aren't a single word
+// can't be tidied and are kept as they are.
+fn tidy_tags(tags: &[String]) -> Vec<String> {
+    let mut tidied: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = parse_tag(tag).unwrap_or_else(|_| tag.clone());
+        if !tidied.contains(&tag) {
+            tidied.push(tag);
+        }
+    }
+    tidied
+}
+
+// Dependencies that can never be met: on the to-do itself, or on an ID that
+// isn't in the list.
+fn bad_dependencies(list: &TodoList, todo: &Todo) -> Vec<u32> {
+    todo.depends_on
+        .iter()
+        .copied()
+        .filter(|&id| id == todo.id || !list.todos.iter().any(|t| t.id == id))
+        .collect()
+}
+
+fn join_ids<'a>(todos: impl IntoIterator<Item = &'a Todo>) -> String {
+    let ids: Vec<String> = todos.into_iter().map(|t| t.id.to_string()).collect();
+    ids.join(", ")
+}
+
+// Looks the list over for the problems that creep in over time or through
+// editing the file by hand: IDs in use twice, the same task added twice,
+// fields that `add` and `edit` would never have written, overdue items, and
+// completed items nobody has cleared away in `stale_days` days.
+pub fn diagnose(list: &TodoList, today: Date, stale_days: u32) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for id in list.duplicate_ids() {
+        let count = list.todos.iter().filter(|t| t.id == id).count();
+        problems.push(Problem::new(
+            format!("{} to-dos share ID {}.", count, id),
+            "`doctor --fix` gives all but the first a new ID.".to_string(),
+            true,
+        ));
+    }
+
+    let mut by_task: BTreeMap<String, Vec<&Todo>> = BTreeMap::new();
+    for todo in &list.todos {
+        let task = todo.task.trim().to_lowercase();
+        if !task.is_empty() {
+            by_task.entry(task).or_default().push(todo);
+        }
+    }
+    for same in by_task.values().filter(|same| same.len() > 1) {
+        problems.push(Problem::new(
+            format!(
+                "To-dos {} are all \"{}\".",
+                join_ids(same.iter().copied()),
+                same[0].task.trim()
+            ),
+            "If they're the same thing, remove the extras with `delete <ID>`.".to_string(),
+            false,
+        ));
+    }
+
+    for todo in &list.todos {
+        let id = todo.id;
+        if todo.task.trim().is_empty() {
+            problems.push(Problem::new(
+                format!("To-do {} has an empty description.", id),
+                format!("Give it one with `rename {} <TASK>`, or delete it.", id),
+                false,
+            ));
+        } else if todo.task.trim() != todo.task {
+            problems.push(Problem::new(
+                format!("To-do {}'s description starts or ends with spaces.", id),
+                "`doctor --fix` trims them.".to_string(),
+                true,
+            ));
+        }
+
+        if tidy_tags(&todo.tags) != todo.tags {
+            problems.push(Problem::new(
+                format!(
+                    "To-do {} has tags that aren't lowercase or appear twice.",
+                    id
+                ),
+                "`doctor --fix` tidies them up.".to_string(),
+                true,
+            ));
+        }
+        for tag in todo.tags.iter().filter(|tag| parse_tag(tag).is_err()) {
+            problems.push(Problem::new(
+                format!(
+                    "To-do {} has the tag \"{}\", which isn't a single word.",
+                    id, tag
+                ),
+                "Change it by editing the to-do file.".to_string(),
+                false,
+            ));
+        }
+
+        if !todo.completed && todo.completed_at.is_some() {
+            problems.push(Problem::new(
+                format!("To-do {} is pending but has a completion time.", id),
+                "`doctor --fix` removes the completion time.".to_string(),
+                true,
+            ));
+        }
+
+        let bad = bad_dependencies(list, todo);
+        if !bad.is_empty() {
+            let ids: Vec<String> = bad.iter().map(|id| id.to_string()).collect();
+            problems.push(Problem::new(
+                format!(
+                    "To-do {} depends on {}, which it can't wait for (itself or missing).",
+                    id,
+                    ids.join(", ")
+                ),
+                "`doctor --fix` drops those dependencies.".to_string(),
+                true,
+            ));
+        }
+    }
+
+    let overdue: Vec<&Todo> = list.todos.iter().filter(|t| t.is_overdue(today)).collect();
+    if !overdue.is_empty() {
+        problems.push(Problem::new(
+            format!(
+                "{} to-dos are overdue (IDs {}).",
+                overdue.len(),
+                join_ids(overdue)
+            ),
+            "Complete them, or push them back with `snooze <ID> <DURATION>`.".to_string(),
+            false,
+        ));
+    }
+
+    let cutoff = Timestamp::now().days_before(stale_days);
+    let stale: Vec<&Todo> = list
+        .todos
+        .iter()
+        .filter(|t| t.completed && t.completed_at.is_some_and(|at| at < cutoff))
+        .collect();
+    if !stale.is_empty() {
+        problems.push(Problem::new(
+            format!(
+                "{} to-dos were completed more than {} days ago (IDs {}).",
+                stale.len(),
+                stale_days,
+                join_ids(stale)
+            ),
+            format!(
+                "Clear them away with `purge --older-than {}` or `archive`.",
+                stale_days
+            ),
+            false,
+        ));
+    }
+
+    problems
+}
+
+// Repairs the problems marked as fixable above, none of which lose anything
+// the user wrote. Returns a description of each repair.
+pub fn repair(list: &mut TodoList) -> Vec<String> {
+    let mut repairs = Vec::new();
+
+    for (old_id, new_id) in list.repair_duplicate_ids() {
+        repairs.push(format!(
+            "Gave a to-do sharing ID {} the new ID {}",
+            old_id, new_id
+        ));
+    }
+
+    for index in 0..list.todos.len() {
+        let bad = bad_dependencies(list, &list.todos[index]);
+        let todo = &mut list.todos[index];
+        let task = todo.task.trim();
+        if !task.is_empty() && task != todo.task {
+            todo.task = task.to_string();
+            repairs.push(format!("Trimmed the description of to-do {}", todo.id));
+        }
+        let tags = tidy_tags(&todo.tags);
+        if tags != todo.tags {
+            todo.tags = tags;
+            repairs.push(format!("Tidied up the tags of to-do {}", todo.id));
+        }
+        if !todo.completed && todo.completed_at.take().is_some() {
+            repairs.push(format!(
+                "Removed the completion time from pending to-do {}",
+                todo.id
+            ));
+        }
+        if !bad.is_empty() {
+            todo.depends_on.retain(|id| !bad.contains(id));
+            repairs.push(format!(
+                "Dropped dependencies that can't be met from to-do {}",
+                todo.id
+            ));
+        }
+    }
+
+    repairs
+}
@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set when Ctrl-C is pressed while interrupts are being held off.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Holds off Ctrl-C (SIGINT) for as long as this value is alive, so a command
+// that has started writing the to-do file, its archive and its undo history
+// gets to finish all of them instead of stopping halfway. A Ctrl-C in the
+// meantime is only noted; check `received` once the writing is done.
+// Everywhere else Ctrl-C stops the program straight away, as usual.
+pub struct DeferInterrupts {
+    #[cfg(unix)]
+    previous: usize,
+}
+
+// std has no way to catch signals, so this talks to the C library directly.
+#[cfg(unix)]
+mod sys {
+    use std::ffi::c_int;
+
+    pub const SIGINT: c_int = 2;
+    // The "handler" that makes a signal be ignored
+    pub const SIG_IGN: usize = 1;
+
+    unsafe extern "C" {
+        // Returns the previous handler. Handlers are passed as addresses.
+        pub fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    // Only stores to an atomic, which is safe to do inside a signal handler.
+    pub extern "C" fn note_interrupt(_signum: c_int) {
+        super::INTERRUPTED.store(true, super::Ordering::Relaxed);
+    }
+}
+
+impl DeferInterrupts {
+    pub fn new() -> DeferInterrupts {
+        #[cfg(unix)]
+        {
+            let handler = sys::note_interrupt as extern "C" fn(std::ffi::c_int) as usize;
+            // SAFETY: the handler only stores to an atomic
+            let previous = unsafe { sys::signal(sys::SIGINT, handler) };
+            DeferInterrupts { previous }
+        }
+        #[cfg(not(unix))]
+        DeferInterrupts {}
+    }
+}
+
+impl Drop for DeferInterrupts {
+    fn drop(&mut self) {
+        // SAFETY: puts back the handler that was there before
+        #[cfg(unix)]
+        unsafe {
+            sys::signal(sys::SIGINT, self.previous);
+        }
+    }
+}
+
+// Ignores Ctrl-C for as long as this value is alive, for while an editor runs
+// in the foreground. The editor gets the keypress and deals with it; this
+// program keeps waiting for the editor instead of dying with the list locked
+// (git does the same). Create it after starting the editor, since ignored
+// signals are passed on to new programs.
+pub struct IgnoreInterrupts {
+    #[cfg(unix)]
+    previous: usize,
+}
+
+impl IgnoreInterrupts {
+    pub fn new() -> IgnoreInterrupts {
+        #[cfg(unix)]
+        {
+            // SAFETY: SIG_IGN installs no code at all
+            let previous = unsafe { sys::signal(sys::SIGINT, sys::SIG_IGN) };
+            IgnoreInterrupts { previous }
+        }
+        #[cfg(not(unix))]
+        IgnoreInterrupts {}
+    }
+}
+
+impl Drop for IgnoreInterrupts {
+    fn drop(&mut self) {
+        // SAFETY: puts back the handler that was there before
+        #[cfg(unix)]
+        unsafe {
+            sys::signal(sys::SIGINT, self.previous);
+        }
+    }
+}
+
+// Whether Ctrl-C was pressed while interrupts were held off.
+pub fn received() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
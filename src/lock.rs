@@ -0,0 +1,90 @@
+use anyhow::{Context, Result, bail};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long to wait for another command to finish with the file before giving up.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+// An exclusive lock on a to-do file, held for as long as this value is alive.
+// The lock is an OS lock (flock on Unix) on a `<file>.lock` file, so the
+// operating system lets go of it when the process ends, even if it was killed
+// with Ctrl-C halfway through a prompt. The file itself is removed on drop;
+// one left behind by a killed process is simply locked again by the next command.
+pub struct FileLock {
+    path: PathBuf,
+    _file: File,
+}
+
+// Whether `file` is still the file at `path`. The holder we were waiting for
+// removes the lock file when it's done, so the file we opened may no longer
+// be the one the next command will open and lock.
+#[cfg(unix)]
+fn is_current(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(ours), Ok(current)) => ours.dev() == current.dev() && ours.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+// Windows doesn't let an open file be removed, so the file can't change under us.
+#[cfg(not(unix))]
+fn is_current(_file: &File, _path: &Path) -> bool {
+    true
+}
+
+impl FileLock {
+    // Takes the lock for `path`, waiting a few seconds if another process has it.
+    pub fn acquire(path: &Path) -> Result<FileLock> {
+        let mut lock_path = path.as_os_str().to_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let started = Instant::now();
+        loop {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&lock_path)
+                .with_context(|| format!("Failed to create lock file {}", lock_path.display()))?;
+            match file.try_lock() {
+                Ok(()) if is_current(&file, &lock_path) => {
+                    // Record who holds the lock, to help when looking into a slow command
+                    let _ = file.set_len(0);
+                    let _ = writeln!(file, "{}", std::process::id());
+                    return Ok(FileLock {
+                        path: lock_path,
+                        _file: file,
+                    });
+                }
+                // Locked a file that was removed in the meantime; start again
+                Ok(()) => continue,
+                Err(TryLockError::WouldBlock) => {
+                    if started.elapsed() >= LOCK_TIMEOUT {
+                        bail!(
+                            "{} is being changed by another command. Try again once it has finished.",
+                            path.display()
+                        );
+                    }
+                    thread::sleep(RETRY_INTERVAL);
+                }
+                Err(TryLockError::Error(e)) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to lock {}", lock_path.display()));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Removed while still locked; the lock itself goes when the file is closed
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -1,22 +1,684 @@
-use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+mod completions;
+mod config;
+mod date;
+mod doctor;
+mod interrupt;
+mod lock;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod store;
+mod tui;
+mod ulid;
+mod undo;
+mod yaml;
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use completions::Shell;
+use date::{Date, Span, Timestamp};
+use interrupt::{DeferInterrupts, IgnoreInterrupts};
+use lock::FileLock;
+use store::Store;
+use undo::UndoEntry;
+
+// Set by --quiet. Confirmations such as "✅ Added ..." are printed with `say!`
+// instead of `println!`, so scripts can turn them off and keep only the output
+// they asked for. Errors and warnings still go to stderr either way.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// Set by --no-emoji (or `no_emoji = true` in the config file), for terminals
+// that can't draw emoji or get the alignment wrong around them.
+static NO_EMOJI: AtomicBool = AtomicBool::new(false);
+
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!("{}", without_emoji(&format!($($arg)*)));
+        }
+    };
+}
+
+// Picks the emoji, or its plain-text stand-in when emoji are turned off.
+fn emoji<'a>(symbol: &'a str, ascii: &'a str) -> &'a str {
+    if NO_EMOJI.load(Ordering::Relaxed) {
+        ascii
+    } else {
+        symbol
+    }
+}
+
+// Confirmations start with an emoji and a space, e.g. "✅ Added ...". With
+// emoji turned off that first word is dropped, so it reads "Added ...". Only
+// the start is touched, so emoji in a task's own description are left alone.
+fn without_emoji(message: &str) -> std::borrow::Cow<'_, str> {
+    if !NO_EMOJI.load(Ordering::Relaxed) || message.starts_with(|c: char| c.is_ascii()) {
+        return message.into();
+    }
+    match message.split_once(' ') {
+        Some((_, rest)) => rest.to_string().into(),
+        None => message.into(),
+    }
+}
+
+// How important a to-do is. The variants are declared from lowest to highest,
+// so the derived Ord puts High above Low.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+enum Priority {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+        };
+        f.write_str(name)
+    }
+}
+
+// How often a recurring to-do comes back after it's completed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum Recurrence {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Recurrence {
+    // The next due date after `date` at this cadence.
+    fn advance(self, date: Date) -> Date {
+        match self {
+            Recurrence::Daily => date.add_days(1),
+            Recurrence::Weekly => date.add_days(7),
+            Recurrence::Monthly => date.add_months(1),
+            Recurrence::Yearly => date.add_months(12),
+        }
+    }
+}
+
+// The orders `list --sort` can show to-dos in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SortKey {
+    Id,
+    Priority,
+    Due,
+}
+
+// The ways `list --group-by` can split the list into sections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum GroupBy {
+    Tags,
+    Priority,
+    Status,
+}
+
+// Which to-dos `list --status` shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum StatusFilter {
+    Pending,
+    Completed,
+    #[default]
+    All,
+}
+
+impl StatusFilter {
+    fn matches(self, todo: &Todo) -> bool {
+        match self {
+            StatusFilter::Pending => !todo.completed,
+            StatusFilter::Completed => todo.completed,
+            StatusFilter::All => true,
+        }
+    }
+}
+
+// The formats the `export` command can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Markdown,
+}
+
+// How `list`, `search` and `stats` print their results (see --format).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    // Each command's usual output
+    Human,
+    Json,
+    Csv,
+    // Aligned columns, as `list` shows by default
+    Table,
+}
 
 // This derive macro allows our struct to be serialized to/from JSON.
 // Clone is useful for creating copies, and Debug for printing.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 struct Todo {
     id: u32,
     task: String,
     completed: bool,
+    // Where the to-do appears in the list, lowest first. Files from before this
+    // field existed load it as 0, which is replaced by the ID (see TodoList::fix_order).
+    #[serde(default)]
+    order: u32,
+    // Optional deadline. Older files don't have this field, so it defaults to None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<Date>,
+    // Older files don't have a priority either, so they load as Medium.
+    #[serde(default)]
+    priority: Priority,
+    // When the to-do was added and when it was last completed.
+    // Both are missing for items created before timestamps were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    completed_at: Option<Timestamp>,
+    // Free-form labels such as "work" or "errands", stored without the leading '#'.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    // Longer free-form details that don't fit in the one-line task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    // Set for chores that should come back after being completed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recur: Option<Recurrence>,
+    // For a completed recurring to-do, the ULID of the copy its schedule was
+    // handed on to, so reopening it can take that back (see TodoList::reopen).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    next_uid: Option<String>,
+    // Pinned to-dos are always listed before the rest.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pinned: bool,
+    // A ULID that identifies the to-do everywhere, unlike `id`, which is short
+    // for typing but only unique within one list. Older files don't have it,
+    // so one is made up when they are next saved (see TodoList::fix_uids).
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    uid: String,
+    // Roughly how long the to-do will take, in minutes, for `stats` to add up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimate_minutes: Option<u32>,
+    // IDs of to-dos in the same list that have to be done before this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<u32>,
+}
+
+impl Todo {
+    // Creates a fresh, uncompleted to-do with every optional field unset.
+    // IDs only ever go up, so ordering by ID puts the new to-do at the end.
+    fn new(id: u32, task: String) -> Todo {
+        Todo {
+            id,
+            task,
+            completed: false,
+            order: id,
+            due: None,
+            priority: Priority::default(),
+            created_at: Some(Timestamp::now()),
+            completed_at: None,
+            tags: Vec::new(),
+            note: None,
+            recur: None,
+            next_uid: None,
+            pinned: false,
+            uid: ulid::generate(),
+            estimate_minutes: None,
+            depends_on: Vec::new(),
+        }
+    }
+
+    // A pending to-do is overdue once its due date has passed.
+    // Items due today are not overdue yet.
+    fn is_overdue(&self, today: Date) -> bool {
+        !self.completed && self.due.is_some_and(|due| due < today)
+    }
+
+    // For a recurring to-do, builds the next occurrence: a fresh, pending copy
+    // whose due date is moved on by one period (counted from today if it had none).
+    // The copy's ID is left at 0 for the caller to allocate.
+    fn next_occurrence(&self) -> Option<Todo> {
+        let recur = self.recur?;
+        let due = recur.advance(self.due.unwrap_or_else(Date::today));
+        Some(Todo {
+            due: Some(due),
+            priority: self.priority,
+            tags: self.tags.clone(),
+            note: self.note.clone(),
+            recur: Some(recur),
+            estimate_minutes: self.estimate_minutes,
+            ..Todo::new(0, self.task.clone())
+        })
+    }
+}
+
+// How a command refers to a to-do: by its ID, or by part of its description.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TodoRef {
+    Id(u32),
+    Text(String),
+}
+
+impl std::str::FromStr for TodoRef {
+    type Err = std::convert::Infallible;
+
+    // Anything that isn't a number is treated as a piece of the description.
+    fn from_str(s: &str) -> Result<TodoRef, Self::Err> {
+        Ok(match s.parse() {
+            Ok(id) => TodoRef::Id(id),
+            Err(_) => TodoRef::Text(s.to_string()),
+        })
+    }
+}
+
+// Completes a to-do for complete and toggle, and says when a recurring one
+// got a next occurrence.
+fn complete(list: &mut TodoList, index: usize) {
+    if let Some(next) = list.complete(index)
+        && let Some(due) = next.due
+    {
+        say!(
+            "🔁 Next \"{}\" added as to-do {} (due {}).",
+            next.task,
+            next.id,
+            due
+        );
+    }
+}
+
+// Reopens a completed to-do for uncomplete, toggle and complete --undo-last,
+// and says what happened, e.g. "Reopened to-do 3" when `done` is "Reopened".
+fn reopen(list: &mut TodoList, index: usize, done: &str) {
+    let (id, task) = (list.todos[index].id, list.todos[index].task.clone());
+    let next = list.reopen(index);
+    say!("↩️ {} to-do {}: \"{}\"", done, id, task);
+    if let Some(next) = next {
+        say!(
+            "🔁 Removed to-do {}, the next \"{}\", which it had been handed on to.",
+            next.id,
+            next.task
+        );
+    }
+}
+
+// Finds the position of the to-do a command refers to. Text matches the
+// description case-insensitively and must match exactly one to-do; otherwise
+// an error (listing the candidates, if several matched) is printed and None returned.
+fn find_todo(todos: &[Todo], target: &TodoRef) -> Option<usize> {
+    match target {
+        TodoRef::Id(id) => {
+            let index = todos.iter().position(|t| t.id == *id);
+            if index.is_none() {
+                eprintln!("Error: To-do with ID {} not found.", id);
+            }
+            index
+        }
+        TodoRef::Text(text) => {
+            // A ULID picks out exactly one to-do
+            if ulid::is_ulid(text)
+                && let Some(index) = todos.iter().position(|t| t.uid.eq_ignore_ascii_case(text))
+            {
+                return Some(index);
+            }
+
+            let wanted = text.to_lowercase();
+            let matches: Vec<usize> = todos
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.task.to_lowercase().contains(&wanted))
+                .map(|(index, _)| index)
+                .collect();
+            match matches.as_slice() {
+                [index] => Some(*index),
+                [] => {
+                    eprintln!("Error: No to-do matches \"{}\".", text);
+                    None
+                }
+                _ => {
+                    eprintln!(
+                        "Error: \"{}\" matches more than one to-do, use an ID instead:",
+                        text
+                    );
+                    for index in matches {
+                        eprintln!("  {}", format_todo(&todos[index]));
+                    }
+                    None
+                }
+            }
+        }
+    }
+}
+
+// One named list of to-dos, such as "work" or "personal".
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct TodoList {
+    // The ID the next new to-do will get. It only ever goes up, so the IDs of
+    // deleted to-dos are never handed out again.
+    #[serde(default)]
+    next_id: u32,
+    todos: Vec<Todo>,
+}
+
+impl TodoList {
+    fn new(todos: Vec<Todo>) -> TodoList {
+        let mut list = TodoList { next_id: 0, todos };
+        list.fix_next_id();
+        list
+    }
+
+    // Makes sure next_id is past every ID in use, in case the file was written
+    // by an older version or edited by hand.
+    fn fix_next_id(&mut self) {
+        let max_id = self.todos.iter().map(|t| t.id).max().unwrap_or(0);
+        self.next_id = self.next_id.max(max_id + 1);
+    }
+
+    // Gives to-dos without an order (from older files) their ID as the order,
+    // then puts the list in display order so every command sees it that way.
+    fn fix_order(&mut self) {
+        for todo in &mut self.todos {
+            if todo.order == 0 {
+                todo.order = todo.id;
+            }
+        }
+        self.todos.sort_by_key(|t| t.order);
+    }
+
+    // Gives to-dos from older files, which have no ULID yet, a new one.
+    fn fix_uids(&mut self) {
+        for todo in self.todos.iter_mut().filter(|t| t.uid.is_empty()) {
+            todo.uid = ulid::generate();
+        }
+    }
+
+    // Numbers the to-dos 1, 2, 3, ... in their current position, after one was moved.
+    fn renumber_order(&mut self) {
+        for (index, todo) in self.todos.iter_mut().enumerate() {
+            todo.order = index as u32 + 1;
+        }
+    }
+
+    // Hands out the ID for a new to-do
+    fn allocate_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    // The dependencies of `todo` that are still pending. Dependencies on
+    // to-dos that have since been deleted don't hold anything up.
+    fn blocking_ids(&self, todo: &Todo) -> Vec<u32> {
+        todo.depends_on
+            .iter()
+            .copied()
+            .filter(|&id| self.todos.iter().any(|t| t.id == id && !t.completed))
+            .collect()
+    }
+
+    // Why `todo` can't be completed yet, if it's waiting on pending to-dos
+    // other than the ones in `completing` (which are being completed with it).
+    fn blocked_reason(&self, todo: &Todo, completing: &BTreeSet<u32>) -> Option<String> {
+        let blocking: Vec<String> = self
+            .blocking_ids(todo)
+            .into_iter()
+            .filter(|id| !completing.contains(id))
+            .map(|id| id.to_string())
+            .collect();
+        if blocking.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "to-do {} is waiting on {} {}, which {} not done yet",
+            todo.id,
+            if blocking.len() == 1 {
+                "to-do"
+            } else {
+                "to-dos"
+            },
+            blocking.join(", "),
+            if blocking.len() == 1 { "is" } else { "are" }
+        ))
+    }
+
+    // Marks the to-do at `index` as done. A recurring to-do hands its schedule
+    // on to a fresh copy for the next occurrence, which goes on the end of the
+    // list so the positions of the others stay valid. Returns that copy.
+    fn complete(&mut self, index: usize) -> Option<Todo> {
+        let todo = &mut self.todos[index];
+        todo.completed = true;
+        todo.completed_at = Some(Timestamp::now());
+        let mut next = todo.next_occurrence()?;
+        todo.recur = None;
+        todo.next_uid = Some(next.uid.clone());
+        next.id = self.allocate_id();
+        next.order = next.id;
+        self.todos.push(next.clone());
+        Some(next)
+    }
+
+    // The counterpart of `complete`: marks the to-do at `index` as not done.
+    // If completing it handed its schedule on to a next occurrence that is
+    // still pending, that copy is removed and the schedule given back, so
+    // reopening doesn't leave the same chore in the list twice. Returns the
+    // copy that was removed.
+    fn reopen(&mut self, index: usize) -> Option<Todo> {
+        let todo = &mut self.todos[index];
+        todo.completed = false;
+        todo.completed_at = None;
+        let uid = todo.next_uid.take()?;
+        let position = self
+            .todos
+            .iter()
+            .position(|t| t.uid == uid && !t.completed)?;
+        let next = self.todos.remove(position);
+        let index = if position < index { index - 1 } else { index };
+        self.todos[index].recur = next.recur;
+        Some(next)
+    }
+
+    // IDs of the pending to-dos that are waiting on another pending to-do.
+    fn blocked_ids(&self) -> BTreeSet<u32> {
+        self.todos
+            .iter()
+            .filter(|t| !t.completed && !self.blocking_ids(t).is_empty())
+            .map(|t| t.id)
+            .collect()
+    }
+
+    // Whether `from` depends on `target`, directly or through other to-dos.
+    fn depends_on(&self, from: u32, target: u32) -> bool {
+        let mut seen = BTreeSet::new();
+        let mut queue = vec![from];
+        while let Some(id) = queue.pop() {
+            if id == target {
+                return true;
+            }
+            if seen.insert(id)
+                && let Some(todo) = self.todos.iter().find(|t| t.id == id)
+            {
+                queue.extend(&todo.depends_on);
+            }
+        }
+        false
+    }
+
+    // Checks dependencies given on the command line: each one has to be an
+    // existing to-do, and none may lead back to `id` itself, or neither could
+    // ever be completed.
+    fn check_dependencies(&self, id: u32, depends_on: &[u32]) -> Result<()> {
+        for &dependency in depends_on {
+            if dependency == id {
+                bail!("to-do {} can't depend on itself", id);
+            }
+            if !self.todos.iter().any(|t| t.id == dependency) {
+                bail!("there is no to-do with ID {} to depend on", dependency);
+            }
+            if self.depends_on(dependency, id) {
+                bail!(
+                    "to-do {} already depends on to-do {}, so it can't be the other way round too",
+                    dependency,
+                    id
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // IDs used by more than one to-do, which can happen if the file was edited by hand.
+    fn duplicate_ids(&self) -> Vec<u32> {
+        let mut seen = std::collections::HashSet::new();
+        let mut duplicates = Vec::new();
+        for todo in &self.todos {
+            if !seen.insert(todo.id) && !duplicates.contains(&todo.id) {
+                duplicates.push(todo.id);
+            }
+        }
+        duplicates
+    }
+
+    // Gives every to-do after the first one with a given ID a fresh ID.
+    // Returns the (old, new) ID of each to-do that was changed.
+    fn repair_duplicate_ids(&mut self) -> Vec<(u32, u32)> {
+        let mut seen = std::collections::HashSet::new();
+        let mut changed = Vec::new();
+        for index in 0..self.todos.len() {
+            let id = self.todos[index].id;
+            if !seen.insert(id) {
+                let new_id = self.allocate_id();
+                self.todos[index].id = new_id;
+                changed.push((id, new_id));
+            }
+        }
+        changed
+    }
+}
+
+// The list commands use when --list isn't given.
+const DEFAULT_LIST: &str = "default";
+
+// The layout of the to-do file this version writes. Bump it whenever a change
+// would confuse an older version reading the file, and teach parse_todo_file
+// how to upgrade files written with the previous one.
+//   0: no version field; a bare array, a single `next_id`/`todos` list, or `lists`
+//   1: `version` and `lists`
+const SCHEMA_VERSION: u32 = 1;
+
+// Everything stored in the to-do file: every list, by name. Each list has its
+// own IDs, so "work" and "personal" can both have a to-do 1.
+#[derive(Serialize, Deserialize, Debug)]
+struct TodoFile {
+    version: u32,
+    lists: BTreeMap<String, TodoList>,
+}
+
+impl Default for TodoFile {
+    fn default() -> Self {
+        TodoFile {
+            version: SCHEMA_VERSION,
+            lists: BTreeMap::new(),
+        }
+    }
+}
+
+// A to-do file written by a newer version of this tool. It's kept apart from
+// JSON errors so the file isn't mistaken for a damaged one and moved aside.
+#[derive(Debug)]
+struct NewerSchemaError {
+    version: u64,
+}
+
+impl std::fmt::Display for NewerSchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "the file uses version {} of the to-do format, but this version of todo only understands up to version {}; please upgrade todo",
+            self.version, SCHEMA_VERSION
+        )
+    }
+}
+
+impl std::error::Error for NewerSchemaError {}
+
+// Parses the contents of a to-do file, upgrading older layouts as it goes.
+// Older versions stored a single list, either as a bare array of todos or as
+// an object with `next_id` and `todos`. Those are still accepted as the default
+// list, and upgraded to the current layout when the file is next saved.
+// `path` is only used to tell YAML files from JSON ones.
+fn parse_todo_file(contents: &str, path: &Path) -> Result<TodoFile> {
+    let value: serde_json::Value = if yaml::is_yaml(path) {
+        yaml::from_str(contents)?
+    } else {
+        serde_json::from_str(contents)?
+    };
+    let version = value
+        .get("version")
+        .map(|v| {
+            v.as_u64()
+                .ok_or_else(|| anyhow!("the `version` field must be a whole number"))
+        })
+        .transpose()?
+        .unwrap_or(0);
+    if version > u64::from(SCHEMA_VERSION) {
+        return Err(NewerSchemaError { version }.into());
+    }
+
+    let mut file = if value.is_array() {
+        let list = TodoList::new(serde_json::from_value(value)?);
+        TodoFile {
+            version: SCHEMA_VERSION,
+            lists: BTreeMap::from([(DEFAULT_LIST.to_string(), list)]),
+        }
+    } else if let Some(lists) = value.get("lists") {
+        TodoFile {
+            version: SCHEMA_VERSION,
+            lists: serde_json::from_value(lists.clone())?,
+        }
+    } else {
+        let list: TodoList = serde_json::from_value(value)?;
+        TodoFile {
+            version: SCHEMA_VERSION,
+            lists: BTreeMap::from([(DEFAULT_LIST.to_string(), list)]),
+        }
+    };
+    for list in file.lists.values_mut() {
+        list.fix_next_id();
+        list.fix_order();
+    }
+    Ok(file)
+}
+
+// Function to load one list of todos from a JSON file
+// If the file or the list doesn't exist yet, it returns an empty list.
+fn load_todos<P: AsRef<Path>>(path: P, name: &str) -> Result<TodoList> {
+    let path = path.as_ref();
+    let mut file = load_todo_file(path)
+        .with_context(|| format!("Could not load your to-dos ({})", path.display()))?;
+    Ok(file
+        .lists
+        .remove(name)
+        .unwrap_or_else(|| TodoList::new(Vec::new())))
 }
 
-// Function to load todos from a JSON file
-// If the file doesn't exist, it returns an empty list.
-fn load_todos<P: AsRef<Path>>(path: P) -> Result<Vec<Todo>> {
+// Loads every list in the to-do file.
+// If the file doesn't exist, it returns a file with no lists.
+fn load_todo_file(path: &Path) -> Result<TodoFile> {
+    store::check_format(path)?;
+
     // Attempt to open the file in read-only mode.
     let file_result = File::open(path);
 
@@ -26,20 +688,38 @@ fn load_todos<P: AsRef<Path>>(path: P) -> Result<Vec<Todo>> {
             file.read_to_string(&mut contents)
                 .context("Failed to read from todo file")?;
 
-            // If the file is empty, return an empty Vec
-            if contents.is_empty() {
-                return Ok(Vec::new());
+            // If the file is empty (or only holds whitespace, which editors
+            // often leave behind), return an empty list
+            if contents.trim().is_empty() {
+                return Ok(TodoFile::default());
             }
 
-            // Deserialize the JSON string into a Vec<Todo>
-            let todos =
-                serde_json::from_str(&contents).context("Failed to parse todo file JSON")?;
-            Ok(todos)
+            // Deserialize the JSON (or YAML) string into a TodoFile
+            match parse_todo_file(&contents, path) {
+                Ok(file) => Ok(file),
+                // Leave a newer file exactly where it is; it isn't damaged
+                Err(e) if e.is::<NewerSchemaError>() => Err(e),
+                Err(e) => {
+                    // A damaged file shouldn't lock the user out of every command.
+                    // Move it aside so nothing is lost, and carry on with an empty list.
+                    let corrupt_path = with_suffix(path, ".corrupt");
+                    std::fs::rename(path, &corrupt_path)
+                        .context("Failed to move corrupted todo file aside")?;
+                    eprintln!(
+                        "Warning: {} is not valid {} ({}). It was moved to {} and a new, empty list was started.",
+                        path.display(),
+                        if yaml::is_yaml(path) { "YAML" } else { "JSON" },
+                        e,
+                        corrupt_path.display()
+                    );
+                    Ok(TodoFile::default())
+                }
+            }
         }
         Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
             // If the file is not found, it's not an error.
-            // Just return an empty list of todos.
-            Ok(Vec::new())
+            // Just return a file without any lists.
+            Ok(TodoFile::default())
         }
         Err(e) => {
             // For any other error, wrap it and return.
@@ -48,132 +728,2800 @@ fn load_todos<P: AsRef<Path>>(path: P) -> Result<Vec<Todo>> {
     }
 }
 
-// Function to save todos to a JSON file
-fn save_todos<P: AsRef<Path>>(path: P, todos: &[Todo]) -> Result<()> {
-    // Open the file with write, create, and truncate options.
-    // This will create the file if it doesn't exist, and clear it before writing.
+// Builds a path next to `path` with `suffix` appended to its file name,
+// e.g. todos.json -> todos.json.tmp
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Builds the path of a file kept alongside the to-do file, with `kind` added
+// before the extension, e.g. todos.json -> todos.archive.json
+fn companion_path(path: &Path, kind: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, kind, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, kind),
+    };
+    path.with_file_name(name)
+}
+
+// Where `archive` keeps finished to-dos: todos.json -> todos.archive.json
+fn archive_path(path: &Path) -> PathBuf {
+    companion_path(path, "archive")
+}
+
+// The command line as typed, minus the program name, for the undo history.
+fn command_line() -> String {
+    std::env::args()
+        .skip(1)
+        .map(|arg| {
+            if arg.contains(char::is_whitespace) {
+                format!("\"{}\"", arg)
+            } else {
+                arg
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// How many previous versions of the to-do file to keep around.
+const BACKUP_COUNT: u32 = 3;
+
+// Path of the nth backup of the to-do file, e.g. todos.json.bak.1
+// Backup 1 is always the most recent one.
+fn backup_path(path: &Path, n: u32) -> PathBuf {
+    with_suffix(path, &format!(".bak.{}", n))
+}
+
+// Copies the current to-do file to todos.json.bak.1, shifting older backups
+// up by one and dropping the oldest. Does nothing if the file doesn't exist yet.
+fn backup_todo_file(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    for n in (1..BACKUP_COUNT).rev() {
+        let older = backup_path(path, n);
+        if older.exists() {
+            std::fs::rename(&older, backup_path(path, n + 1))
+                .with_context(|| format!("Failed to rotate backup {}", older.display()))?;
+        }
+    }
+
+    std::fs::copy(path, backup_path(path, 1)).context("Failed to copy todo file to backup")?;
+    Ok(())
+}
+
+// Function to save one list of todos to a JSON file
+// The other lists in the file are read back in and kept as they are. Callers
+// hold the file lock, so nothing else can have changed them in the meantime.
+// With `sync`, the function only returns once the data is physically on disk.
+fn save_todos<P: AsRef<Path>>(path: P, name: &str, list: &TodoList, sync: bool) -> Result<()> {
+    let path = path.as_ref();
+    let save = || {
+        let mut file = load_todo_file(path)?;
+        let mut list = list.clone();
+        list.fix_uids();
+        file.lists.insert(name.to_string(), list);
+        write_todo_file(path, &file, sync)
+    };
+    save().with_context(|| format!("Could not save your to-dos ({})", path.display()))
+}
+
+// Writes every list to the to-do file.
+fn write_todo_file(path: &Path, file: &TodoFile, sync: bool) -> Result<()> {
+    store::check_format(path)?;
+
+    // Serialize the TodoFile into a JSON string, or YAML for a .yaml file.
+    let json_string = if yaml::is_yaml(path) {
+        yaml::to_string(file).context("Failed to serialize todos to YAML")?
+    } else {
+        serde_json::to_string_pretty(file).context("Failed to serialize todos to JSON")?
+    };
+
+    // Write to a temporary file in the same directory first, then rename it over
+    // the real file. The rename is atomic on the same filesystem, so the to-do
+    // file always holds either the old list or the new one, never half of a write.
+    let tmp_path = with_suffix(path, ".tmp");
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(path)
-        .context("Failed to open or create todo file for writing")?;
+        .open(&tmp_path)
+        .context("Failed to open or create temporary todo file for writing")?;
 
-    // Serialize the Vec<Todo> into a JSON string.
-    let json_string =
-        serde_json::to_string_pretty(todos).context("Failed to serialize todos to JSON")?;
+    // A freshly created file gets the default permissions, so without this the
+    // rename would quietly undo a `chmod 600` on the to-do file. Copying them
+    // before anything is written means the contents are never more exposed.
+    if let Ok(metadata) = std::fs::metadata(path) {
+        file.set_permissions(metadata.permissions())
+            .context("Failed to copy the todo file's permissions")?;
+    }
 
-    // Write the JSON string to the file.
+    // Write the JSON string to the temporary file.
     file.write_all(json_string.as_bytes())
-        .context("Failed to write to todo file")?;
+        .context("Failed to write to temporary todo file")?;
+    // Without this the new contents may still only be in the OS cache when the
+    // rename lands, and a power cut could leave an empty or partial file behind.
+    if sync {
+        file.sync_all()
+            .context("Failed to flush temporary todo file to disk")?;
+    }
+    drop(file);
 
-    Ok(())
-}
+    // Keep a copy of the previous list in case this save was a mistake.
+    // Losing the backup isn't worth failing the save over, so just warn.
+    if let Err(e) = backup_todo_file(path) {
+        eprintln!("Warning: could not back up the todo file: {:#}", e);
+    }
 
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-struct Cli {
-    #[command(subcommand)]
-    command: Commands,
+    std::fs::rename(&tmp_path, path).context("Failed to replace todo file")?;
+
+    // The rename itself is only durable once the directory entry is written out
+    #[cfg(unix)]
+    if sync {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(dir)
+            .and_then(|d| d.sync_all())
+            .context("Failed to flush the todo file's directory to disk")?;
+    }
+
+    Ok(())
 }
 
-#[derive(Subcommand)]
-enum Commands {
-    /// Add a new to-do item
-    Add {
-        /// The task description
-        task: String,
-    },
-    /// List all to-do items
-    List,
-    /// Edit an existing to-do item's description
-    Edit {
-        /// The ID of the to-do to edit
-        id: u32,
-        /// The new task description
-        #[arg(short, long)]
-        new_task: String,
-    },
-    /// Mark a to-do item as complete
-    Complete {
-        /// The ID of the to-do to complete
-        id: u32,
-    },
-    /// Delete a to-do item
-    Delete {
-        /// The ID of the to-do to delete
-        id: u32,
-    },
+// Expands a leading `~` to the user's home directory.
+// Shells do this for unquoted arguments, but not for environment variables.
+fn expand_tilde(path: &Path) -> PathBuf {
+    if let Ok(rest) = path.strip_prefix("~")
+        && let Some(home) = std::env::var_os("HOME")
+    {
+        return PathBuf::from(home).join(rest);
+    }
+    path.to_path_buf()
 }
 
-fn main() -> Result<()> {
+// Works out which to-do file to use.
+// Precedence is: the --file flag, then the TODO_FILE environment variable,
+// then the `file` setting from the config file, then todos.json in the current directory.
+fn resolve_todo_file(flag: Option<PathBuf>, configured: Option<PathBuf>) -> PathBuf {
     const TODO_FILE: &str = "todos.json";
 
-    let cli = Cli::parse();
-    let mut todos = load_todos(TODO_FILE)?;
+    let path = flag
+        .or_else(|| {
+            std::env::var_os("TODO_FILE")
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        })
+        .or(configured)
+        .unwrap_or_else(|| PathBuf::from(TODO_FILE));
+    expand_tilde(&path)
+}
 
-    match cli.command {
-        Commands::Add { task } => {
-            // Find the highest existing ID and add 1 for the new ID
-            let new_id = todos.iter().map(|t| t.id).max().unwrap_or(0) + 1;
-            let new_todo = Todo {
-                id: new_id,
-                task,
-                completed: false,
-            };
-            println!(
-                "✅ Added new to-do: \"{}\" (ID: {})",
-                new_todo.task, new_todo.id
-            );
-            todos.push(new_todo);
+// How `list` draws each item: the markers for done and pending items and the
+// date format. The defaults are "[x]", "[ ]" and YYYY-MM-DD; the config file
+// and the list flags can change them.
+#[derive(Clone)]
+struct ListStyle {
+    done_marker: String,
+    pending_marker: String,
+    // A strftime-style pattern (see Date::format), or None for YYYY-MM-DD
+    date_format: Option<String>,
+    // The to-dos waiting on others (see TodoList::blocked_ids), marked with 🔒
+    blocked: BTreeSet<u32>,
+}
+
+impl Default for ListStyle {
+    fn default() -> ListStyle {
+        ListStyle {
+            done_marker: "[x]".to_string(),
+            pending_marker: "[ ]".to_string(),
+            date_format: None,
+            blocked: BTreeSet::new(),
         }
+    }
+}
 
-        Commands::List => {
-            if todos.is_empty() {
-                println!("No to-dos yet! Add one with the 'add' command.");
-            } else {
-                println!("--- Your To-Do List ---");
-                for todo in todos {
-                    let status = if todo.completed { "[x]" } else { "[ ]" };
-                    println!("{} {}: {}", status, todo.id, todo.task);
-                }
-            }
-            // No need to save, since we didn't change anything
-            return Ok(());
+impl ListStyle {
+    fn status(&self, todo: &Todo) -> &str {
+        if todo.completed {
+            &self.done_marker
+        } else {
+            &self.pending_marker
         }
+    }
 
-        Commands::Edit { id, new_task } => {
-            if let Some(todo) = todos.iter_mut().find(|t| t.id == id) {
-                todo.task = new_task.clone();
-                println!("📝 Edited to-do {}: \"{}\"", id, todo.task);
-            } else {
-                eprintln!("Error: To-do with ID {} not found.", id);
-            }
+    fn lock(&self, todo: &Todo) -> &'static str {
+        if self.blocked.contains(&todo.id) {
+            emoji("🔒 ", "(blocked) ")
+        } else {
+            ""
         }
+    }
 
-        Commands::Complete { id } => {
-            if let Some(todo) = todos.iter_mut().find(|t| t.id == id) {
-                todo.completed = true;
-                println!("🎉 Completed to-do {}: \"{}\"", id, todo.task);
-            } else {
-                eprintln!("Error: To-do with ID {} not found.", id);
-            }
+    fn date(&self, date: Date) -> String {
+        match &self.date_format {
+            Some(pattern) => date.format(pattern),
+            None => date.to_string(),
         }
+    }
+}
+
+// Formats a single to-do as one line of list output, e.g. "[ ] 3: File taxes (due 2025-04-15)"
+fn format_todo(todo: &Todo) -> String {
+    format_todo_with(todo, &ListStyle::default())
+}
+
+// Like format_todo, but with the markers and date format given by `style`.
+fn format_todo_with(todo: &Todo, style: &ListStyle) -> String {
+    let status = style.status(todo);
+    // Flag high-priority items so they stand out in the list
+    let marker = if todo.priority == Priority::High {
+        emoji("❗ ", "! ")
+    } else {
+        ""
+    };
+    let overdue = if todo.is_overdue(Date::today()) {
+        emoji("⚠️ OVERDUE ", "OVERDUE ")
+    } else {
+        ""
+    };
+
+    let pin = if todo.pinned {
+        emoji("📌 ", "* ")
+    } else {
+        ""
+    };
+
+    let mut line = format!(
+        "{} {}: {}{}{}{}{}",
+        status,
+        todo.id,
+        pin,
+        style.lock(todo),
+        overdue,
+        marker,
+        todo.task
+    );
+    for tag in &todo.tags {
+        line.push_str(&format!(" #{}", tag));
+    }
+    if let Some(due) = todo.due {
+        line.push_str(&format!(" (due {})", style.date(due)));
+    }
+    line
+}
+
+// Writes a number of minutes the way people say it, e.g. "45m", "2h" or "3h 45m".
+fn format_minutes(minutes: u32) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h {}m", h, m),
+    }
+}
+
+// The details `list --verbose` shows under an item, e.g.
+// "priority high, due 2025-04-15, tags #work, created 2025-04-01T09:30:00Z"
+fn describe_details(todo: &Todo, style: &ListStyle) -> String {
+    let mut details = vec![format!("priority {}", todo.priority)];
+    if let Some(due) = todo.due {
+        details.push(format!("due {}", style.date(due)));
+    }
+    if !todo.tags.is_empty() {
+        let tags: Vec<String> = todo.tags.iter().map(|t| format!("#{}", t)).collect();
+        details.push(format!("tags {}", tags.join(" ")));
+    }
+    if let Some(estimate) = todo.estimate_minutes {
+        details.push(format!("estimate {}", format_minutes(estimate)));
+    }
+    if let Some(created_at) = todo.created_at {
+        details.push(format!("created {}", created_at));
+    }
+    if let Some(completed_at) = todo.completed_at {
+        details.push(format!("completed {}", completed_at));
+    }
+    details.join(", ")
+}
+
+// Lays the to-dos out as an aligned table with status, ID, priority, due date
+// and task columns. Returns the header line and one line per to-do, in order.
+fn format_table(todos: &[&Todo], style: &ListStyle) -> (String, Vec<String>) {
+    let today = Date::today();
+    let due_text = |todo: &Todo| match todo.due {
+        Some(due) if todo.is_overdue(today) => format!("{} (overdue)", style.date(due)),
+        Some(due) => style.date(due),
+        None => String::new(),
+    };
 
-        Commands::Delete { id } => {
-            let initial_len = todos.len();
-            todos.retain(|t| t.id != id);
+    // Each column is as wide as its widest value, header included.
+    // Widths are counted in characters, since markers and dates may not be ASCII.
+    let status_width = [&style.done_marker, &style.pending_marker]
+        .iter()
+        .map(|m| m.chars().count())
+        .max()
+        .unwrap_or(0);
+    let id_width = todos
+        .iter()
+        .map(|t| t.id.to_string().len())
+        .chain(["ID".len()])
+        .max()
+        .unwrap_or(0);
+    let priority_width = "PRIORITY".len();
+    let due_width = todos
+        .iter()
+        .map(|t| due_text(t).chars().count())
+        .chain(["DUE".len()])
+        .max()
+        .unwrap_or(0);
 
-            if todos.len() < initial_len {
-                println!("🗑️ Deleted to-do with ID {}.", id);
+    let header = format!(
+        "{:status_width$} {:>id_width$}  {:<priority_width$}  {:<due_width$}  TASK",
+        "", "ID", "PRIORITY", "DUE"
+    );
+    let rows = todos
+        .iter()
+        .map(|todo| {
+            let pin = if todo.pinned {
+                emoji("📌 ", "* ")
             } else {
-                eprintln!("Error: To-do with ID {} not found.", id);
+                ""
+            };
+            let mut task = format!("{}{}{}", pin, style.lock(todo), todo.task);
+            for tag in &todo.tags {
+                task.push_str(&format!(" #{}", tag));
             }
-        }
+            format!(
+                "{:<status_width$} {:>id_width$}  {:<priority_width$}  {:<due_width$}  {}",
+                style.status(todo),
+                todo.id,
+                todo.priority.to_string(),
+                due_text(todo),
+                task
+            )
+        })
+        .collect();
+    (header, rows)
+}
+
+// Prints the lines, through a pager like `git` does when they're going to a
+// terminal and don't fit on it. The pager is $PAGER, or `less -FRX` if that
+// isn't set (an empty PAGER turns paging off). If the pager can't be started,
+// the lines are just printed.
+fn page(lines: &[String], allowed: bool) -> Result<()> {
+    let text = lines
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+    let fits = || tui::terminal_rows().is_none_or(|rows| lines.len() < rows);
+    if !allowed || !std::io::stdout().is_terminal() || fits() {
+        print!("{}", text);
+        return Ok(());
+    }
+
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -FRX".to_string());
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        print!("{}", text);
+        return Ok(());
+    };
+    let child = std::process::Command::new(program)
+        .args(words)
+        .stdin(std::process::Stdio::piped())
+        .spawn();
+    let Ok(mut child) = child else {
+        print!("{}", text);
+        return Ok(());
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe, which isn't an error
+        let _ = stdin.write_all(text.as_bytes());
     }
+    child.wait().context("Failed to wait for the pager")?;
+    Ok(())
+}
 
-    // Save the potentially modified list of todos back to the file
-    save_todos(TODO_FILE, &todos)?;
+// When the to-do file was last changed, or None while it doesn't exist.
+fn modified_time(path: &Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
 
+// Clears the screen and draws the list the way `list` does, for `watch`.
+fn render_watch(
+    path: &Path,
+    store: &dyn Store,
+    list_name: &str,
+    style: &ListStyle,
+    color: bool,
+) -> Result<()> {
+    let list = store.load(list_name)?;
+    let style = &ListStyle {
+        blocked: list.blocked_ids(),
+        ..style.clone()
+    };
+    if std::io::stdout().is_terminal() {
+        print!("\x1b[H\x1b[2J");
+    }
+    println!(
+        "--- Your To-Do List ---  (watching {}, Ctrl-C to stop)",
+        path.display()
+    );
+    if list.todos.is_empty() {
+        println!("No to-dos yet! Add one with the 'add' command.");
+        return Ok(());
+    }
+    let mut view: Vec<&Todo> = list.todos.iter().collect();
+    view.sort_by_key(|t| !t.pinned);
+    let (header, rows) = format_table(&view, style);
+    println!("{}", header);
+    for (todo, line) in view.iter().zip(&rows) {
+        if color {
+            println!("{}", colorize(todo, line));
+        } else {
+            println!("{}", line);
+        }
+    }
+    let completed = view.iter().filter(|t| t.completed).count();
+    println!(
+        "{}/{} completed ({:.0}%)",
+        completed,
+        view.len(),
+        completed as f64 / view.len() as f64 * 100.0
+    );
     Ok(())
 }
+
+// Draws the list, then redraws it every time the to-do file changes. There's
+// no file-watching crate available, so this checks the file's modification
+// time twice a second. A change is only drawn once the file has stopped
+// changing for a moment, so a burst of saves causes one redraw, not a flicker.
+fn watch(
+    path: &Path,
+    store: &dyn Store,
+    list_name: &str,
+    style: &ListStyle,
+    color: bool,
+) -> Result<()> {
+    const POLL: std::time::Duration = std::time::Duration::from_millis(500);
+    const SETTLE: std::time::Duration = std::time::Duration::from_millis(200);
+
+    let mut seen = modified_time(path);
+    render_watch(path, store, list_name, style, color)?;
+    loop {
+        std::thread::sleep(POLL);
+        let mut current = modified_time(path);
+        if current == seen {
+            continue;
+        }
+        // Wait for the writes to settle down
+        loop {
+            std::thread::sleep(SETTLE);
+            let latest = modified_time(path);
+            if latest == current {
+                break;
+            }
+            current = latest;
+        }
+        seen = current;
+        // A file caught halfway through being replaced is picked up next time
+        if let Err(e) = render_watch(path, store, list_name, style, color) {
+            eprintln!("Warning: {}", friendly_message(&e));
+        }
+    }
+}
+
+// Whether to use colors in the output. Following the NO_COLOR convention
+// (https://no-color.org), any non-empty NO_COLOR turns them off, and so does
+// writing to something other than a terminal.
+fn use_color() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && std::io::stdout().is_terminal()
+}
+
+// Wraps a formatted line in ANSI color codes: completed items are dimmed and
+// struck through, and pending items that are high priority or overdue are red.
+fn colorize(todo: &Todo, line: &str) -> String {
+    const DIM_STRIKE: &str = "\x1b[2;9m";
+    const RED: &str = "\x1b[31m";
+    const RESET: &str = "\x1b[0m";
+
+    if todo.completed {
+        format!("{}{}{}", DIM_STRIKE, line, RESET)
+    } else if todo.priority == Priority::High || todo.is_overdue(Date::today()) {
+        format!("{}{}{}", RED, line, RESET)
+    } else {
+        line.to_string()
+    }
+}
+
+// Parses a --due value, which may be relative to today (see Date::parse_relative).
+fn parse_due(s: &str) -> Result<Date> {
+    Date::parse_relative(s, Date::today())
+}
+
+// Checks a --list name. Names are kept as typed, but must not be blank.
+fn parse_list_name(s: &str) -> Result<String, String> {
+    let name = s.trim();
+    if name.is_empty() {
+        return Err("the list name can't be empty".to_string());
+    }
+    Ok(name.to_string())
+}
+
+// Normalizes a tag given on the command line, so "#Work" and "work" are the same tag.
+fn parse_tag(s: &str) -> Result<String, String> {
+    let tag = s.trim().trim_start_matches('#').to_lowercase();
+    if tag.is_empty() || tag.contains(char::is_whitespace) {
+        return Err(format!("invalid tag '{}', tags must be a single word", s));
+    }
+    Ok(tag)
+}
+
+// Quotes a CSV field if it contains a comma, quote or line break (RFC 4180).
+// Quotes inside the field are escaped by doubling them.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Writes the to-dos as CSV with an `id,task,completed` header row.
+fn write_csv<'a, W: Write>(out: &mut W, todos: impl IntoIterator<Item = &'a Todo>) -> Result<()> {
+    writeln!(out, "id,task,completed")?;
+    for todo in todos {
+        writeln!(
+            out,
+            "{},{},{}",
+            todo.id,
+            csv_field(&todo.task),
+            todo.completed
+        )?;
+    }
+    Ok(())
+}
+
+// Writes the to-dos as a GitHub-style Markdown task list, "- [x] task" for
+// completed items and "- [ ] task" for pending ones. When `grouped` is set,
+// pending and completed items are listed under their own headings.
+fn write_markdown<W: Write>(out: &mut W, todos: &[Todo], grouped: bool) -> Result<()> {
+    // A line break would end the list item early, so keep each task on one line
+    let item = |todo: &Todo| {
+        let check = if todo.completed { "x" } else { " " };
+        format!("- [{}] {}", check, todo.task.replace('\n', " "))
+    };
+
+    if !grouped {
+        for todo in todos {
+            writeln!(out, "{}", item(todo))?;
+        }
+        return Ok(());
+    }
+
+    let (done, pending): (Vec<&Todo>, Vec<&Todo>) = todos.iter().partition(|t| t.completed);
+    let mut first = true;
+    for (heading, group) in [("Pending", pending), ("Completed", done)] {
+        if group.is_empty() {
+            continue;
+        }
+        if !first {
+            writeln!(out)?;
+        }
+        first = false;
+        writeln!(out, "## {}", heading)?;
+        writeln!(out)?;
+        for todo in group {
+            writeln!(out, "{}", item(todo))?;
+        }
+    }
+    Ok(())
+}
+
+// Sorts a view of the to-dos without touching the order stored in the file.
+// The sorts are stable, so ties keep their original relative order.
+fn sort_todos(todos: &mut [&Todo], key: SortKey) {
+    match key {
+        SortKey::Id => todos.sort_by_key(|t| t.id),
+        // Highest priority first
+        SortKey::Priority => todos.sort_by_key(|t| std::cmp::Reverse(t.priority)),
+        // Earliest due date first, with undated items at the end
+        SortKey::Due => todos.sort_by_key(|t| (t.due.is_none(), t.due)),
+    }
+}
+
+// Puts a view of the to-dos in the order `list` shows them: sorted by `key`
+// if there is one, reversed if asked, and with pinned to-dos first.
+fn arrange(view: &mut [&Todo], key: Option<SortKey>, reverse: bool) {
+    if let Some(key) = key {
+        sort_todos(view, key);
+    }
+    if reverse {
+        view.reverse();
+    }
+    // Pinned items go first whatever the sort; the sort is stable, so
+    // both groups keep the order chosen above
+    view.sort_by_key(|t| !t.pinned);
+}
+
+// Turns a 1-based position in a plain `list` into the ID of the to-do shown
+// there, for commands given --index. `shown` is the IDs in that order. An
+// error is printed and None returned if there's no such position.
+fn id_at_position(shown: &[u32], target: &TodoRef) -> Option<TodoRef> {
+    let TodoRef::Id(position) = *target else {
+        eprintln!("Error: --index takes positions such as 3, not text.");
+        return None;
+    };
+    match shown.get((position as usize).wrapping_sub(1)) {
+        Some(&id) => Some(TodoRef::Id(id)),
+        None => {
+            eprintln!(
+                "Error: There is no to-do at position {} (the list shows {}).",
+                position,
+                shown.len()
+            );
+            None
+        }
+    }
+}
+
+// The IDs of the to-dos in the order a plain `list` shows them.
+fn shown_ids(todos: &[Todo], key: Option<SortKey>) -> Vec<u32> {
+    let mut view: Vec<&Todo> = todos.iter().collect();
+    arrange(&mut view, key, false);
+    view.iter().map(|t| t.id).collect()
+}
+
+// Splits a view of the to-dos into headed sections, keeping the view's order
+// within each one. Returns each section's heading and the positions (in `todos`)
+// of its members; empty sections are left out. When grouping by tag, an item
+// with several tags appears under each of them.
+fn group_todos(todos: &[&Todo], key: GroupBy) -> Vec<(String, Vec<usize>)> {
+    let positions = |belongs: &dyn Fn(&Todo) -> bool| -> Vec<usize> {
+        (0..todos.len()).filter(|&i| belongs(todos[i])).collect()
+    };
+    let groups = match key {
+        GroupBy::Tags => {
+            let tags: BTreeSet<&str> = todos
+                .iter()
+                .flat_map(|t| t.tags.iter().map(String::as_str))
+                .collect();
+            let mut groups: Vec<(String, Vec<usize>)> = tags
+                .into_iter()
+                .map(|tag| {
+                    let members = positions(&|t| t.tags.iter().any(|x| x == tag));
+                    (format!("#{}", tag), members)
+                })
+                .collect();
+            groups.push(("No tag".to_string(), positions(&|t| t.tags.is_empty())));
+            groups
+        }
+        GroupBy::Priority => [
+            ("High priority", Priority::High),
+            ("Medium priority", Priority::Medium),
+            ("Low priority", Priority::Low),
+        ]
+        .into_iter()
+        .map(|(heading, priority)| (heading.to_string(), positions(&|t| t.priority == priority)))
+        .collect(),
+        GroupBy::Status => vec![
+            ("Pending".to_string(), positions(&|t| !t.completed)),
+            ("Completed".to_string(), positions(&|t| t.completed)),
+        ],
+    };
+    groups
+        .into_iter()
+        .filter(|(_, members)| !members.is_empty())
+        .collect()
+}
+
+// Asks a yes/no question on the terminal and returns true only for "y" or "yes".
+// When stdin isn't a terminal the answer is always no, so piped input can
+// never confirm a destructive action by accident.
+fn confirm(prompt: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "Not asking for confirmation because stdin is not a terminal; pass --yes to proceed."
+        );
+        return Ok(false);
+    }
+
+    print!("{} [y/N] ", prompt);
+    std::io::stdout()
+        .flush()
+        .context("Failed to write prompt")?;
+
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .context("Failed to read answer")?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+// The longest task description accepted unless the config file says otherwise.
+const MAX_TASK_LENGTH: usize = 500;
+
+// Trims the whitespace around a task description and checks it's neither
+// empty nor longer than `max_length` characters.
+fn clean_task(task: &str, max_length: usize) -> Result<String> {
+    let task = task.trim();
+    if task.is_empty() {
+        bail!("The task description can't be empty");
+    }
+    let length = task.chars().count();
+    if length > max_length {
+        bail!(
+            "The task description is {} characters long, but at most {} are allowed",
+            length,
+            max_length
+        );
+    }
+    Ok(task.to_string())
+}
+
+// Details written into the task text itself, todo.txt style (see parse_inline).
+#[derive(Debug, Default)]
+struct InlineDetails {
+    priority: Option<Priority>,
+    tags: Vec<String>,
+    due: Option<Date>,
+}
+
+// Picks `!high` (or `!low`, `!medium`), `@tag` and `due:DATE` words out of a
+// task description, e.g. "pay rent !high @home due:2025-04-01". Returns the
+// description without them and the details they gave. Anything else that
+// merely looks similar, like "Hurry!" or "!!", is left in the text; a `due:`
+// word with a date that can't be understood is an error rather than text.
+fn parse_inline(task: &str) -> Result<(String, InlineDetails)> {
+    let mut details = InlineDetails::default();
+    // Only the tokens are taken out; the rest of the text, spacing and
+    // indentation included, is kept exactly as typed
+    let mut text = String::new();
+    let mut kept_up_to = 0;
+    for (start, word) in words(task) {
+        if let Some(priority) = word
+            .strip_prefix('!')
+            .and_then(|name| Priority::from_str(name, true).ok())
+        {
+            details.priority = Some(priority);
+        } else if let Some(tag) = word.strip_prefix('@').and_then(|tag| parse_tag(tag).ok()) {
+            if !details.tags.contains(&tag) {
+                details.tags.push(tag);
+            }
+        } else if let Some(date) = word.strip_prefix("due:").filter(|d| !d.is_empty()) {
+            details.due = Some(parse_due(date).with_context(|| format!("In \"{}\"", word))?);
+        } else {
+            continue;
+        }
+        let (start, end) = token_with_space(task, start, start + word.len());
+        let start = start.max(kept_up_to);
+        text.push_str(&task[kept_up_to..start]);
+        kept_up_to = end;
+    }
+    text.push_str(&task[kept_up_to..]);
+    Ok((text, details))
+}
+
+// The words of `text` with the byte offset each one starts at.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push((s, &text[s..i]));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &text[s..]));
+    }
+    words
+}
+
+// Widens the token at start..end to take the spaces before it, or the ones
+// after it if it's first on its line, so removing it leaves the words on
+// either side spaced as they were and keeps the line's indentation.
+fn token_with_space(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let is_space = |c: char| c.is_whitespace() && c != '\n' && c != '\r';
+    let before = text[..start].trim_end_matches(is_space);
+    if !before.is_empty() && !before.ends_with(['\n', '\r']) {
+        return (before.len(), end);
+    }
+    let after = text[end..].trim_start_matches(is_space);
+    (start, text.len() - after.len())
+}
+
+// Reads a task description from standard input, dropping the trailing newline.
+fn read_task_from_stdin() -> Result<String> {
+    let mut task = String::new();
+    std::io::stdin()
+        .read_to_string(&mut task)
+        .context("Failed to read task from standard input")?;
+    let task = task.trim_end_matches(['\n', '\r']).to_string();
+    if task.trim().is_empty() {
+        bail!("No task text was given on standard input");
+    }
+    Ok(task)
+}
+
+// The help text left at the bottom of the file `edit --interactive` opens.
+const EDITOR_INSTRUCTIONS: &str = "\
+# Edit the to-do above. The first line is the task description; anything
+# after it (following a blank line) is the note. Lines starting with '#'
+# are ignored. Leave the file as it is to cancel.";
+
+// Opens the to-do's description and note in the user's editor, like
+// `git commit` does, and returns the edited description and note. Returns
+// None if the text came back unchanged. Fails if the editor does.
+fn edit_in_editor(todo: &Todo) -> Result<Option<(String, Option<String>)>> {
+    let mut original = format!("{}\n", todo.task);
+    if let Some(note) = &todo.note {
+        original.push_str(&format!("\n{}\n", note));
+    }
+    original.push_str(&format!("\n{}\n", EDITOR_INSTRUCTIONS));
+
+    let path = std::env::temp_dir().join(format!("todo-edit-{}.txt", std::process::id()));
+    std::fs::write(&path, &original)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    // $VISUAL and $EDITOR may hold arguments too, e.g. "code --wait"
+    let editor = std::env::var("VISUAL")
+        .ok()
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(&path)
+        .spawn()
+        .and_then(|mut child| {
+            let _ignore = IgnoreInterrupts::new();
+            child.wait()
+        })
+        .with_context(|| format!("Failed to start the editor '{}'", editor));
+
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    let status = status?;
+    if !status.success() {
+        bail!(
+            "The editor exited with {}, so the to-do was left as it was",
+            status
+        );
+    }
+    let edited = edited.with_context(|| format!("Failed to read {}", path.display()))?;
+    if edited == original {
+        return Ok(None);
+    }
+
+    let text: Vec<&str> = edited
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    let task = text.first().copied().unwrap_or_default().to_string();
+    let note = text
+        .get(1..)
+        .unwrap_or_default()
+        .join("\n")
+        .trim()
+        .to_string();
+    Ok(Some((task, (!note.is_empty()).then_some(note))))
+}
+
+// Shown at the end of `--help`, so the richer options are easy to discover.
+const HELP_EXAMPLES: &str = "\
+Examples:
+  rust_todo_cli add \"File taxes\" --priority high --due tomorrow --tag money
+  rust_todo_cli list --status pending --sort due
+  rust_todo_cli list --group-by tags --verbose
+  rust_todo_cli complete 3 5
+  rust_todo_cli edit 2 --interactive
+  rust_todo_cli --list work today
+  rust_todo_cli undo
+
+In the list:
+  [x] / [ ]   completed / pending (see done_marker and pending_marker in the config)
+  ❗          high priority
+  ⚠️ OVERDUE   the due date has passed
+  📌          pinned, always listed first
+  🔒          waiting on a to-do it depends on
+  In a terminal, high-priority and overdue items are red and completed
+  items are dimmed. Set NO_COLOR or pass --no-emoji for plain output.
+
+Run `rust_todo_cli help <COMMAND>` for the options of each command.";
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None, after_help = HELP_EXAMPLES)]
+struct Cli {
+    /// Path to the to-do file (defaults to $TODO_FILE, then the config file, then todos.json)
+    #[arg(short, long, global = true)]
+    file: Option<PathBuf>,
+
+    /// Give to-dos that share an ID fresh IDs instead of just warning about them
+    #[arg(long, global = true)]
+    fix_ids: bool,
+
+    /// Which named list in the to-do file to use (defaults to "default")
+    #[arg(long, global = true, value_name = "NAME", value_parser = parse_list_name)]
+    list: Option<String>,
+
+    /// Don't print confirmation messages, only the requested output and errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Print the full details of any error, for bug reports
+    #[arg(long, global = true)]
+    debug: bool,
+
+    /// Make sure changes are physically written to disk before finishing (slower)
+    #[arg(long, global = true)]
+    sync: bool,
+
+    /// Show what a command would change without writing anything to disk
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Print plain text instead of emoji
+    #[arg(long, global = true)]
+    no_emoji: bool,
+
+    /// How list, search and stats print their results
+    #[arg(long = "format", global = true, value_enum, value_name = "FORMAT")]
+    output_format: Option<OutputFormat>,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Add one or more new to-do items
+    Add {
+        /// The task descriptions, one to-do is created for each ("-" reads one from stdin)
+        #[arg(required = true, num_args = 1..)]
+        tasks: Vec<String>,
+        /// Due date: YYYY-MM-DD, today, tomorrow, a weekday, or a span like "in 3 days"
+        #[arg(short, long, value_parser = parse_due)]
+        due: Option<Date>,
+        /// How important the task is
+        #[arg(short, long, value_enum, default_value_t = Priority::Medium)]
+        priority: Priority,
+        /// Attach a tag to the task (can be repeated)
+        #[arg(short, long = "tag", value_name = "TAG", value_parser = parse_tag)]
+        tags: Vec<String>,
+        /// A longer note with extra details
+        #[arg(short, long)]
+        note: Option<String>,
+        /// Refuse to add a task whose description is already on the list
+        #[arg(long)]
+        unique: bool,
+        /// Record the task as already done
+        #[arg(long)]
+        completed: bool,
+        /// Bring the task back with a new due date each time it's completed
+        #[arg(short, long, value_enum)]
+        recur: Option<Recurrence>,
+        /// Roughly how long the task will take, in minutes
+        #[arg(short, long, value_name = "MINUTES")]
+        estimate: Option<u32>,
+        /// The ID of a to-do that has to be completed first (can be repeated)
+        #[arg(long = "depends-on", value_name = "ID")]
+        depends_on: Vec<u32>,
+        /// Print only the new to-do's ID (one per line), for scripts
+        #[arg(long)]
+        print_id: bool,
+        /// Keep !priority, @tag and due:DATE words in the description as typed
+        #[arg(long)]
+        literal: bool,
+    },
+    /// List all to-do items
+    List {
+        /// Show the items ordered by this key instead of the stored order (or the config's default_sort)
+        #[arg(short, long, value_enum)]
+        sort: Option<SortKey>,
+        /// Show the items in the opposite order (after --sort, if given)
+        #[arg(short, long)]
+        reverse: bool,
+        /// Only show pending or completed items
+        #[arg(long, value_enum, default_value_t = StatusFilter::All)]
+        status: StatusFilter,
+        /// Only show items carrying this tag
+        #[arg(short, long, value_parser = parse_tag)]
+        tag: Option<String>,
+        /// Show at most this many items (after filtering and sorting)
+        #[arg(short, long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        limit: Option<usize>,
+        /// Also show each item's note, indented under it
+        #[arg(long)]
+        long: bool,
+        /// Also show each item's priority, due date, tags and timestamps, indented under it
+        #[arg(short, long)]
+        verbose: bool,
+        /// Print the items as JSON instead of a human-readable list
+        #[arg(long)]
+        json: bool,
+        /// Print one simple line per item instead of an aligned table, without the summary
+        #[arg(long)]
+        plain: bool,
+        /// Show the items in sections, one per tag, priority or status
+        #[arg(short, long, value_enum, conflicts_with = "json")]
+        group_by: Option<GroupBy>,
+        /// Only show items created on or after this date (same formats as add --due)
+        #[arg(long, value_name = "DATE", value_parser = parse_due)]
+        since: Option<Date>,
+        /// Only show items created on or before this date
+        #[arg(long, value_name = "DATE", value_parser = parse_due)]
+        until: Option<Date>,
+        /// The marker for completed items (default "[x]")
+        #[arg(long, value_name = "TEXT")]
+        done_marker: Option<String>,
+        /// The marker for pending items (default "[ ]")
+        #[arg(long, value_name = "TEXT")]
+        pending_marker: Option<String>,
+        /// How to show due dates, e.g. "%d %b" (default "%Y-%m-%d")
+        #[arg(long, value_name = "FORMAT")]
+        date_format: Option<String>,
+        /// Print straight to the terminal, even when the list doesn't fit on screen
+        #[arg(long)]
+        no_pager: bool,
+    },
+    /// Show everything about a single to-do item
+    Show {
+        /// The ID of the to-do to show, or part of its description
+        id: TodoRef,
+    },
+    /// Show pending to-do items grouped by when they're due
+    Due,
+    /// Show only the pending to-do items due today or overdue
+    Today,
+    /// Show the completed to-do items, most recently completed first
+    Log {
+        /// Only show items completed in the last this many days
+        #[arg(short, long)]
+        days: Option<u32>,
+    },
+    /// Show the single most urgent pending to-do item
+    Next,
+    /// Find to-do items whose description contains some text
+    Search {
+        /// The text to look for (case-insensitive)
+        query: String,
+    },
+    /// Edit an existing to-do item's description, due date, priority, tags or estimate
+    #[command(group(
+        clap::ArgGroup::new("changes")
+            .required(true)
+            .multiple(true)
+            .args(["new_task", "due", "priority", "tags", "estimate", "depends_on", "interactive"])
+    ))]
+    Edit {
+        /// The ID of the to-do to edit, or part of its description
+        id: TodoRef,
+        /// The new task description
+        #[arg(short, long)]
+        new_task: Option<String>,
+        /// The new due date (same formats as for add)
+        #[arg(short, long, value_parser = parse_due)]
+        due: Option<Date>,
+        /// The new priority
+        #[arg(short, long, value_enum)]
+        priority: Option<Priority>,
+        /// Replace the task's tags with these (can be repeated)
+        #[arg(short, long = "tag", value_name = "TAG", value_parser = parse_tag)]
+        tags: Vec<String>,
+        /// The new estimate, in minutes
+        #[arg(short, long, value_name = "MINUTES")]
+        estimate: Option<u32>,
+        /// Replace the to-dos this one waits for with these IDs (can be repeated)
+        #[arg(long = "depends-on", value_name = "ID")]
+        depends_on: Vec<u32>,
+        /// Edit the description and note in $EDITOR
+        #[arg(short, long, conflicts_with = "new_task")]
+        interactive: bool,
+    },
+    /// Change a to-do item's description (short for `edit <ID> --new-task`)
+    Rename {
+        /// The ID of the to-do to rename, or part of its description
+        id: TodoRef,
+        /// The new task description
+        task: String,
+    },
+    /// Push a to-do item's due date back, e.g. `snooze 3 2d`
+    Snooze {
+        /// The ID of the to-do to snooze, or part of its description
+        id: TodoRef,
+        /// How far to push it back: 3d, 2w, 1m and so on (from today if it has no due date)
+        duration: Span,
+    },
+    /// Attach a note to a to-do item, replacing any existing note
+    Note {
+        /// The ID of the to-do to annotate, or part of its description
+        id: TodoRef,
+        /// The note text (an empty string removes the note)
+        note: String,
+    },
+    /// Mark one or more to-do items as complete
+    Complete {
+        /// The IDs of the to-dos to complete, or parts of their descriptions
+        #[arg(
+            required_unless_present_any = ["all", "tag", "undo_last"],
+            conflicts_with_all = ["all", "tag", "undo_last"],
+            num_args = 1..
+        )]
+        ids: Vec<TodoRef>,
+        /// Take the IDs as positions in the list as `list` shows it: 3 is the third item
+        #[arg(short, long, conflicts_with_all = ["all", "tag", "undo_last"])]
+        index: bool,
+        /// Complete every pending to-do
+        #[arg(long, conflicts_with_all = ["tag", "undo_last"])]
+        all: bool,
+        /// Complete every pending to-do carrying this tag
+        #[arg(short, long, value_parser = parse_tag, conflicts_with = "undo_last")]
+        tag: Option<String>,
+        /// Reopen the to-do that was completed most recently instead
+        #[arg(long)]
+        undo_last: bool,
+        /// Complete everything without asking for confirmation (used with --all)
+        #[arg(short, long)]
+        yes: bool,
+        /// Complete the to-dos even if ones they depend on are still pending
+        #[arg(long)]
+        force: bool,
+    },
+    /// Pin a to-do item so it's always listed first
+    Pin {
+        /// The ID of the to-do to pin, or part of its description
+        id: TodoRef,
+    },
+    /// Unpin a to-do item so it's listed in its usual place again
+    Unpin {
+        /// The ID of the to-do to unpin, or part of its description
+        id: TodoRef,
+    },
+    /// Reverse the most recent change to the list
+    Undo,
+    /// Mark a completed to-do item as not done yet
+    Uncomplete {
+        /// The ID of the to-do to reopen, or part of its description
+        id: TodoRef,
+    },
+    /// Flip a to-do item between complete and not done
+    Toggle {
+        /// The ID of the to-do to toggle, or part of its description
+        id: TodoRef,
+        /// Complete the to-do even if ones it depends on are still pending
+        #[arg(long)]
+        force: bool,
+    },
+    /// Delete one or more to-do items
+    Delete {
+        /// The IDs of the to-dos to delete, or parts of their descriptions
+        #[arg(
+            required_unless_present_any = ["all_completed", "tag"],
+            conflicts_with_all = ["all_completed", "tag"],
+            num_args = 1..
+        )]
+        ids: Vec<TodoRef>,
+        /// Take the IDs as positions in the list as `list` shows it: 3 is the third item
+        #[arg(short, long, conflicts_with_all = ["all_completed", "tag"])]
+        index: bool,
+        /// Delete every completed to-do instead
+        #[arg(long, conflicts_with = "tag")]
+        all_completed: bool,
+        /// Delete every to-do carrying this tag instead
+        #[arg(short, long, value_parser = parse_tag)]
+        tag: Option<String>,
+        /// Delete without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Browse and update the list in a full-screen terminal view
+    Tui,
+    /// Show the list and redraw it whenever the to-do file changes, until Ctrl-C
+    Watch,
+    /// Print a shell completion script, e.g. `completions bash > ~/.bash_completion.d/todo`
+    #[command(hide = true)]
+    Completions {
+        /// The shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Remove every completed to-do item
+    ClearCompleted,
+    /// Remove completed to-do items that were finished more than some number of days ago
+    Purge {
+        /// Only remove items completed more than this many days ago
+        #[arg(long = "older-than", value_name = "DAYS")]
+        older_than_days: u32,
+    },
+    /// Delete every to-do item and start over with an empty list
+    Reset {
+        /// Reset without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Put the list back the way it was in the most recent backup (e.g. todos.json.bak.1)
+    Restore {
+        /// Restore without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Move completed to-do items into the archive file (e.g. todos.archive.json)
+    Archive,
+    /// Check the list for problems, such as IDs in use twice, and suggest fixes
+    Doctor {
+        /// Repair the problems that can be fixed safely
+        #[arg(long)]
+        fix: bool,
+        /// Report completed items that were finished more than this many days ago
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        completed_older_than: u32,
+    },
+    /// Show a summary of how the list is going
+    Stats {
+        /// Also break the counts down by tag
+        #[arg(long)]
+        by_tag: bool,
+    },
+    /// Print just the number of to-do items, for scripts and prompts
+    Count {
+        /// Which items to count
+        #[arg(value_enum, default_value_t = StatusFilter::Pending)]
+        status: StatusFilter,
+    },
+    /// Move a to-do item to another position in the list
+    Move {
+        /// The ID of the to-do to move, or part of its description
+        id: TodoRef,
+        /// The new position, starting at 1 (out-of-range values go to the start or end)
+        to: usize,
+    },
+    /// Renumber the to-do items 1, 2, 3, ... in their current order
+    Renumber,
+    /// Add every line of a todo.txt style file as a new to-do item
+    Import {
+        /// The file to read, with one task per line (or a to-do file with --merge)
+        path: PathBuf,
+        /// Read another to-do file and add its items to this list, with new IDs
+        #[arg(long)]
+        merge: bool,
+        /// With --merge, leave out items whose description is already on the list
+        #[arg(long)]
+        skip_existing: bool,
+    },
+    /// Export the to-do list to another format
+    Export {
+        /// The format to write
+        #[arg(value_enum)]
+        format: ExportFormat,
+        /// Write to this file instead of standard output
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+        /// For markdown, list pending and completed items under separate headings
+        #[arg(short, long)]
+        group: bool,
+    },
+}
+
+impl Commands {
+    // Commands that only look at the list, and so never need to save it.
+    fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Commands::List { .. }
+                | Commands::Search { .. }
+                | Commands::Due
+                | Commands::Today
+                | Commands::Log { .. }
+                | Commands::Next
+                | Commands::Show { .. }
+                | Commands::Stats { .. }
+                | Commands::Count { .. }
+                | Commands::Export { .. }
+                | Commands::Watch
+                | Commands::Doctor { fix: false, .. }
+                | Commands::Completions { .. }
+        )
+    }
+}
+
+// Turns an error into a single line for the user: what couldn't be done,
+// then the underlying reason, e.g.
+// "Could not save your to-dos (todos.json): permission denied".
+// The steps in between are only interesting when debugging, so they're left
+// to --debug.
+fn friendly_message(error: &anyhow::Error) -> String {
+    let root = error.root_cause();
+    let reason = match root.downcast_ref::<std::io::Error>() {
+        Some(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            "no such file or directory".to_string()
+        }
+        Some(e) => e.kind().to_string(),
+        None => root.to_string(),
+    };
+    if error.chain().count() == 1 {
+        reason
+    } else {
+        format!("{}: {}", error, reason)
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let debug = cli.debug;
+    match run(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            if debug {
+                eprintln!("Error: {:?}", e);
+            } else {
+                eprintln!("Error: {}", friendly_message(&e));
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+
+    // Completions don't need the to-do file at all, so handle them before loading it
+    if let Commands::Completions { shell } = cli.command {
+        print!("{}", completions::generate(shell, Cli::command()));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let config = config::load()?;
+    NO_EMOJI.store(
+        cli.no_emoji || config.no_emoji == Some(true),
+        Ordering::Relaxed,
+    );
+    let todo_file = resolve_todo_file(cli.file, config.file);
+    let sync = cli.sync || config.sync == Some(true);
+    let store = store::open(todo_file.clone(), sync)?;
+
+    // Hold the lock from loading until saving, so two commands running at the
+    // same time can't both load the list and then overwrite each other's changes.
+    let _lock = if !cli.command.is_read_only() || cli.fix_ids {
+        Some(FileLock::acquire(&todo_file)?)
+    } else {
+        None
+    };
+    let max_task_length = config.max_task_length.unwrap_or(MAX_TASK_LENGTH);
+    let list_name = cli.list.as_deref().unwrap_or(DEFAULT_LIST).to_string();
+    let mut list = store.load(&list_name)?;
+    // New IDs never start below the configured base
+    if let Some(start) = config.id_start {
+        list.next_id = list.next_id.max(start);
+    }
+
+    // Commands find to-dos by ID, so two with the same ID would make them act
+    // on whichever comes first. Catch that before anything else happens.
+    let duplicates = list.duplicate_ids();
+    if !duplicates.is_empty() {
+        if cli.fix_ids {
+            for (old_id, new_id) in list.repair_duplicate_ids() {
+                say!(
+                    "🔧 Gave a to-do sharing ID {} the new ID {}.",
+                    old_id,
+                    new_id
+                );
+            }
+            if !cli.dry_run {
+                store.save(&list_name, &list)?;
+            }
+        } else if !matches!(cli.command, Commands::Doctor { .. }) {
+            // doctor reports them itself
+            let ids: Vec<String> = duplicates.iter().map(|id| id.to_string()).collect();
+            eprintln!(
+                "Warning: {} has more than one to-do with ID {}. Run with --fix-ids to repair it.",
+                todo_file.display(),
+                ids.join(", ")
+            );
+        }
+    }
+
+    let output_format = cli.output_format.unwrap_or(OutputFormat::Human);
+
+    // How the list looked before this command, for the undo history
+    let is_undo = matches!(cli.command, Commands::Undo);
+    let before = list.clone();
+
+    // Set when some of several to-dos given to one command couldn't be found.
+    // The others are still changed and saved, but the exit status reports the failure.
+    let mut missing = false;
+    // Ctrl-C is held off from the first write until the last one (see
+    // DeferInterrupts). Most commands only start writing after the match;
+    // archive starts inside it.
+    let mut writing: Option<DeferInterrupts> = None;
+
+    match cli.command {
+        Commands::Add {
+            tasks,
+            due,
+            priority,
+            tags,
+            note,
+            unique,
+            completed,
+            recur,
+            estimate,
+            depends_on,
+            print_id,
+            literal,
+        } => {
+            // Check every description first, so one bad task doesn't leave
+            // the others reported as added when nothing gets saved
+            list.check_dependencies(0, &depends_on)?;
+            let mut cleaned = Vec::new();
+            for mut task in tasks {
+                // A lone "-" means the task text comes from standard input
+                if task == "-" {
+                    task = read_task_from_stdin()?;
+                }
+                let (task, inline) = if literal {
+                    (task, InlineDetails::default())
+                } else {
+                    parse_inline(&task)?
+                };
+                cleaned.push((clean_task(&task, max_task_length)?, inline));
+            }
+
+            // The options apply to every task added in this invocation, but
+            // details written into one task's text are more specific, so they
+            // win for that task (tags are combined)
+            for (task, inline) in cleaned {
+                if unique {
+                    let wanted = task.trim().to_lowercase();
+                    if let Some(existing) = list
+                        .todos
+                        .iter()
+                        .find(|t| t.task.trim().to_lowercase() == wanted)
+                    {
+                        eprintln!(
+                            "Error: \"{}\" is already on the list as to-do {}.",
+                            task.trim(),
+                            existing.id
+                        );
+                        // Like a missing ID: the other tasks are still added,
+                        // but the exit status says one wasn't
+                        missing = true;
+                        continue;
+                    }
+                }
+
+                let mut all_tags = tags.clone();
+                all_tags.extend(inline.tags.into_iter().filter(|t| !tags.contains(t)));
+                let new_todo = Todo {
+                    due: inline.due.or(due),
+                    priority: inline.priority.unwrap_or(priority),
+                    tags: all_tags,
+                    note: note.clone(),
+                    completed,
+                    completed_at: completed.then(Timestamp::now),
+                    recur,
+                    estimate_minutes: estimate,
+                    depends_on: depends_on.clone(),
+                    ..Todo::new(list.allocate_id(), task)
+                };
+                if print_id {
+                    // Printed even with --quiet, since it's the output that was asked for
+                    println!("{}", new_todo.id);
+                } else if completed {
+                    say!(
+                        "✅ Added already completed to-do: \"{}\" (ID: {})",
+                        new_todo.task,
+                        new_todo.id
+                    );
+                } else {
+                    say!(
+                        "✅ Added new to-do: \"{}\" (ID: {})",
+                        new_todo.task,
+                        new_todo.id
+                    );
+                }
+                list.todos.push(new_todo);
+            }
+        }
+
+        Commands::List {
+            sort,
+            reverse,
+            status,
+            tag,
+            limit,
+            long,
+            verbose,
+            json,
+            plain,
+            group_by,
+            since,
+            until,
+            done_marker,
+            pending_marker,
+            date_format,
+            no_pager,
+        } => {
+            // Sections have no place in JSON or CSV records. clap already turns
+            // down --group-by with --json, but --format is a global option.
+            if group_by.is_some() && matches!(output_format, OutputFormat::Json | OutputFormat::Csv)
+            {
+                bail!(
+                    "--group-by can't be used with --format {}; each record already has its tags, priority and completed fields to group by",
+                    if output_format == OutputFormat::Json {
+                        "json"
+                    } else {
+                        "csv"
+                    }
+                );
+            }
+
+            // Flags win over the config file, which wins over the defaults
+            let defaults = ListStyle::default();
+            let style = ListStyle {
+                done_marker: done_marker
+                    .or(config.done_marker)
+                    .unwrap_or(defaults.done_marker),
+                pending_marker: pending_marker
+                    .or(config.pending_marker)
+                    .unwrap_or(defaults.pending_marker),
+                date_format: date_format.or(config.date_format),
+                blocked: list.blocked_ids(),
+            };
+            let mut view: Vec<&Todo> = list
+                .todos
+                .iter()
+                .filter(|t| status.matches(t))
+                .filter(|t| tag.as_ref().is_none_or(|tag| t.tags.contains(tag)))
+                .collect();
+            if since.is_some() || until.is_some() {
+                // Items from before creation times were recorded can't be placed
+                // in the range, so they're left out, but not silently
+                let undated = view.iter().filter(|t| t.created_at.is_none()).count();
+                if undated > 0 {
+                    eprintln!(
+                        "Warning: {} to-dos have no creation date and were left out.",
+                        undated
+                    );
+                }
+                view.retain(|t| {
+                    t.created_at.is_some_and(|created| {
+                        let day = created.local_date();
+                        since.is_none_or(|since| day >= since)
+                            && until.is_none_or(|until| day <= until)
+                    })
+                });
+            }
+            arrange(&mut view, sort.or(config.default_sort), reverse);
+            // Counted before --limit, so the summary covers every item the filters matched
+            let shown_total = view.len();
+            let shown_completed = view.iter().filter(|t| t.completed).count();
+            let mut hidden = 0;
+            if let Some(limit) = limit
+                && view.len() > limit
+            {
+                hidden = view.len() - limit;
+                view.truncate(limit);
+            }
+
+            // Collected first, so a long list can be shown through a pager
+            let mut output = Vec::new();
+            if json || output_format == OutputFormat::Json {
+                // Pure JSON for scripts, so no headers or friendly messages
+                let json_string = serde_json::to_string_pretty(&view)
+                    .context("Failed to serialize todos to JSON")?;
+                output.push(json_string);
+            } else if output_format == OutputFormat::Csv {
+                let mut buffer = Vec::new();
+                write_csv(&mut buffer, view.iter().copied())?;
+                output.extend(String::from_utf8_lossy(&buffer).lines().map(String::from));
+            } else if list.todos.is_empty() {
+                output.push("No to-dos yet! Add one with the 'add' command.".to_string());
+            } else if view.is_empty() {
+                let which = match status {
+                    StatusFilter::Pending => "pending",
+                    StatusFilter::Completed => "completed",
+                    StatusFilter::All => "matching",
+                };
+                output.push(format!("No {} to-dos.", which));
+            } else {
+                let color = config.color != Some(false) && use_color();
+                output.push("--- Your To-Do List ---".to_string());
+                let lines = if plain {
+                    view.iter().map(|t| format_todo_with(t, &style)).collect()
+                } else {
+                    let (header, rows) = format_table(&view, &style);
+                    output.push(header);
+                    rows
+                };
+                let print_item = |output: &mut Vec<String>, index: usize| {
+                    let (todo, line) = (view[index], &lines[index]);
+                    if color {
+                        output.push(colorize(todo, line));
+                    } else {
+                        output.push(line.clone());
+                    }
+                    if verbose {
+                        output.push(format!("    {}", describe_details(todo, &style)));
+                    }
+                    if long && let Some(note) = &todo.note {
+                        for note_line in note.lines() {
+                            output.push(format!("    {}", note_line));
+                        }
+                    }
+                };
+                match group_by {
+                    Some(key) => {
+                        for (heading, members) in group_todos(&view, key) {
+                            output.push(String::new());
+                            output.push(format!("{}:", heading));
+                            for index in members {
+                                print_item(&mut output, index);
+                            }
+                        }
+                    }
+                    None => {
+                        for index in 0..view.len() {
+                            print_item(&mut output, index);
+                        }
+                    }
+                }
+                if hidden > 0 {
+                    output.push(format!("... and {} more", hidden));
+                }
+                if !plain {
+                    output.push(format!(
+                        "{}/{} completed ({:.0}%)",
+                        shown_completed,
+                        shown_total,
+                        shown_completed as f64 / shown_total as f64 * 100.0
+                    ));
+                }
+            }
+            page(&output, !no_pager)?;
+            // No need to save, since we didn't change anything
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Show { id } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            let todo = &list.todos[index];
+
+            let status = if todo.completed {
+                "completed"
+            } else {
+                "pending"
+            };
+            println!("ID:        {}", todo.id);
+            if !todo.uid.is_empty() {
+                println!("UID:       {}", todo.uid);
+            }
+            println!("Task:      {}", todo.task);
+            println!("Status:    {}", status);
+            println!("Priority:  {}", todo.priority);
+            if todo.pinned {
+                println!("Pinned:    yes");
+            }
+            if let Some(due) = todo.due {
+                println!("Due:       {}", due);
+            }
+            if !todo.tags.is_empty() {
+                let tags: Vec<String> = todo.tags.iter().map(|t| format!("#{}", t)).collect();
+                println!("Tags:      {}", tags.join(" "));
+            }
+            if let Some(estimate) = todo.estimate_minutes {
+                println!("Estimate:  {}", format_minutes(estimate));
+            }
+            if !todo.depends_on.is_empty() {
+                let ids: Vec<String> = todo.depends_on.iter().map(|id| id.to_string()).collect();
+                let blocking = list.blocking_ids(todo);
+                if blocking.is_empty() {
+                    println!("Depends:   {} (all done)", ids.join(", "));
+                } else {
+                    println!("Depends:   {} (blocked)", ids.join(", "));
+                }
+            }
+            if let Some(created_at) = todo.created_at {
+                println!("Created:   {}", created_at);
+            }
+            if let Some(completed_at) = todo.completed_at {
+                println!("Completed: {}", completed_at);
+            }
+            if let Some(note) = &todo.note {
+                println!("Note:");
+                for note_line in note.lines() {
+                    println!("    {}", note_line);
+                }
+            }
+            // Showing is read-only, so there's nothing to save
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Due => {
+            let today = Date::today();
+            let end_of_week = today.end_of_week();
+            let mut pending: Vec<&Todo> = list.todos.iter().filter(|t| !t.completed).collect();
+            sort_todos(&mut pending, SortKey::Due);
+
+            if pending.is_empty() {
+                println!("No pending to-dos.");
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            // Which group a due date falls in, as an index into `headings`
+            let headings = ["Overdue", "Today", "This Week", "Later", "No date"];
+            let group_of = |due: Option<Date>| match due {
+                Some(due) if due < today => 0,
+                Some(due) if due == today => 1,
+                Some(due) if due <= end_of_week => 2,
+                Some(_) => 3,
+                None => 4,
+            };
+            // Sorting by due date above keeps each group in date order
+            let mut first = true;
+            for (index, heading) in headings.iter().enumerate() {
+                let group: Vec<&Todo> = pending
+                    .iter()
+                    .copied()
+                    .filter(|t| group_of(t.due) == index)
+                    .collect();
+                if group.is_empty() {
+                    continue;
+                }
+                if !first {
+                    println!();
+                }
+                first = false;
+                println!("--- {} ---", heading);
+                for todo in group {
+                    println!("{}", format_todo(todo));
+                }
+            }
+            // The due view is read-only, so there's nothing to save
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Today => {
+            let today = Date::today();
+            let mut due: Vec<&Todo> = list
+                .todos
+                .iter()
+                .filter(|t| !t.completed && t.due.is_some_and(|due| due <= today))
+                .collect();
+            // Overdue items first, oldest deadline at the top
+            sort_todos(&mut due, SortKey::Due);
+
+            if due.is_empty() {
+                println!("Nothing due today.");
+            } else {
+                println!("--- Due Today ---");
+                for todo in due {
+                    println!("{}", format_todo(todo));
+                }
+            }
+            // The today view is read-only, so there's nothing to save
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Log { days } => {
+            // Items completed before completion times were recorded can't be
+            // placed in time, so they're left out, but not silently
+            let undated = list
+                .todos
+                .iter()
+                .filter(|t| t.completed && t.completed_at.is_none())
+                .count();
+            if undated > 0 {
+                eprintln!(
+                    "Warning: {} completed to-dos have no completion date and were left out.",
+                    undated
+                );
+            }
+            let cutoff = days.map(|days| Timestamp::now().days_before(days));
+            let mut done: Vec<(Timestamp, &Todo)> = list
+                .todos
+                .iter()
+                .filter(|t| t.completed)
+                .filter_map(|t| Some((t.completed_at?, t)))
+                .filter(|(at, _)| cutoff.is_none_or(|cutoff| *at >= cutoff))
+                .collect();
+            done.sort_by_key(|(at, _)| std::cmp::Reverse(*at));
+
+            let style = ListStyle {
+                date_format: config.date_format,
+                ..ListStyle::default()
+            };
+            if done.is_empty() {
+                match days {
+                    Some(days) => println!("Nothing completed in the last {} days.", days),
+                    None => println!("Nothing completed yet."),
+                }
+            } else {
+                match days {
+                    Some(days) => println!("--- Completed in the Last {} Days ---", days),
+                    None => println!("--- Completed ---"),
+                }
+                for (at, todo) in done {
+                    println!(
+                        "{}  {}: {}",
+                        style.date(at.local_date()),
+                        todo.id,
+                        todo.task
+                    );
+                }
+            }
+            // The log is read-only, so there's nothing to save
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Next => {
+            // Highest priority first, then the earliest due date (undated items
+            // last), then the oldest ID
+            let next = list
+                .todos
+                .iter()
+                .filter(|t| !t.completed)
+                .min_by_key(|t| (std::cmp::Reverse(t.priority), t.due.is_none(), t.due, t.id));
+            match next {
+                Some(todo) => println!("{}", format_todo(todo)),
+                None => println!("{}All done!", emoji("🎉 ", "")),
+            }
+            // Picking the next item is read-only, so there's nothing to save
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Search { query } => {
+            let query = query.to_lowercase();
+            let matches: Vec<&Todo> = list
+                .todos
+                .iter()
+                .filter(|t| t.task.to_lowercase().contains(&query))
+                .collect();
+
+            match output_format {
+                OutputFormat::Json => {
+                    let json_string = serde_json::to_string_pretty(&matches)
+                        .context("Failed to serialize todos to JSON")?;
+                    println!("{}", json_string);
+                }
+                OutputFormat::Csv => write_csv(&mut std::io::stdout(), matches)?,
+                _ if matches.is_empty() => println!("No matching to-dos found."),
+                OutputFormat::Table => {
+                    let (header, rows) = format_table(&matches, &ListStyle::default());
+                    println!("{}", header);
+                    for row in rows {
+                        println!("{}", row);
+                    }
+                }
+                OutputFormat::Human => {
+                    for todo in matches {
+                        println!("{}", format_todo(todo));
+                    }
+                }
+            }
+            // Searching is read-only, so there's nothing to save
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Archive => {
+            let (done, pending): (Vec<Todo>, Vec<Todo>) =
+                list.todos.drain(..).partition(|t| t.completed);
+            list.todos = pending;
+
+            if done.is_empty() {
+                say!("Nothing to archive, no to-dos are completed.");
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            // Save the archive first, so a failure here leaves the main list untouched
+            let archive_file = archive_path(&todo_file);
+            let archive_store = store::open(archive_file.clone(), sync)?;
+            let mut archive = archive_store.load(&list_name)?;
+            let count = done.len();
+            archive.todos.extend(done);
+            archive.fix_next_id();
+            if !cli.dry_run {
+                writing = Some(DeferInterrupts::new());
+                archive_store.save(&list_name, &archive)?;
+            }
+            say!(
+                "📦 Archived {} completed to-dos to {}.",
+                count,
+                archive_file.display()
+            );
+        }
+
+        Commands::Count { status } => {
+            let count = list.todos.iter().filter(|t| status.matches(t)).count();
+            println!("{}", count);
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Stats { by_tag } => {
+            let total = list.todos.len();
+            let completed = list.todos.iter().filter(|t| t.completed).count();
+            let pending = total - completed;
+            let percent = if total == 0 {
+                0.0
+            } else {
+                completed as f64 / total as f64 * 100.0
+            };
+
+            // Only mention overdue items if the list uses due dates at all
+            let overdue = list.todos.iter().any(|t| t.due.is_some()).then(|| {
+                let today = Date::today();
+                list.todos.iter().filter(|t| t.is_overdue(today)).count()
+            });
+
+            // Likewise for estimates. Pending items without one can't be added
+            // up, so they're counted instead of being guessed at
+            let remaining = list
+                .todos
+                .iter()
+                .any(|t| t.estimate_minutes.is_some())
+                .then(|| {
+                    let pending: Vec<&Todo> = list.todos.iter().filter(|t| !t.completed).collect();
+                    let minutes: u32 = pending.iter().filter_map(|t| t.estimate_minutes).sum();
+                    let unestimated = pending
+                        .iter()
+                        .filter(|t| t.estimate_minutes.is_none())
+                        .count();
+                    (minutes, unestimated)
+                });
+
+            // (tag, total, completed) for each tag, with untagged to-dos last
+            // (shown as "(none)"). A to-do with several tags counts towards each.
+            let tags = by_tag.then(|| {
+                let mut counts: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+                for todo in list.todos.iter().filter(|t| !t.tags.is_empty()) {
+                    for tag in &todo.tags {
+                        let count = counts.entry(tag).or_default();
+                        count.0 += 1;
+                        count.1 += usize::from(todo.completed);
+                    }
+                }
+                let mut tags: Vec<(Option<String>, usize, usize)> = counts
+                    .into_iter()
+                    .map(|(tag, (total, completed))| (Some(tag.to_string()), total, completed))
+                    .collect();
+                let untagged: Vec<&Todo> =
+                    list.todos.iter().filter(|t| t.tags.is_empty()).collect();
+                if !untagged.is_empty() {
+                    let completed = untagged.iter().filter(|t| t.completed).count();
+                    tags.push((None, untagged.len(), completed));
+                }
+                tags
+            });
+
+            match output_format {
+                OutputFormat::Json | OutputFormat::Csv => {
+                    let mut stats = vec![
+                        ("total", serde_json::json!(total)),
+                        ("completed", serde_json::json!(completed)),
+                        ("pending", serde_json::json!(pending)),
+                        (
+                            "progress_percent",
+                            serde_json::json!(percent.round() as u64),
+                        ),
+                    ];
+                    if let Some(overdue) = overdue {
+                        stats.push(("overdue", serde_json::json!(overdue)));
+                    }
+                    if let Some((minutes, unestimated)) = remaining {
+                        stats.push(("remaining_minutes", serde_json::json!(minutes)));
+                        stats.push(("unestimated", serde_json::json!(unestimated)));
+                    }
+                    if output_format == OutputFormat::Json {
+                        let mut object: serde_json::Map<String, serde_json::Value> = stats
+                            .into_iter()
+                            .map(|(name, value)| (name.to_string(), value))
+                            .collect();
+                        if let Some(tags) = &tags {
+                            let by_tag: serde_json::Map<String, serde_json::Value> = tags
+                                .iter()
+                                .map(|(tag, total, completed)| {
+                                    let counts = serde_json::json!({
+                                        "total": total,
+                                        "completed": completed,
+                                        "pending": total - completed,
+                                    });
+                                    (tag.as_deref().unwrap_or("(none)").to_string(), counts)
+                                })
+                                .collect();
+                            object.insert("by_tag".to_string(), by_tag.into());
+                        }
+                        let json_string = serde_json::to_string_pretty(&object)
+                            .context("Failed to serialize stats to JSON")?;
+                        println!("{}", json_string);
+                    } else if let Some(tags) = &tags {
+                        // One table per file, so --by-tag prints just the breakdown
+                        println!("tag,total,completed,pending");
+                        for (tag, total, completed) in tags {
+                            println!(
+                                "{},{},{},{}",
+                                csv_field(tag.as_deref().unwrap_or("(none)")),
+                                total,
+                                completed,
+                                total - completed
+                            );
+                        }
+                    } else {
+                        println!("stat,value");
+                        for (name, value) in stats {
+                            println!("{},{}", name, value);
+                        }
+                    }
+                }
+                OutputFormat::Human | OutputFormat::Table => {
+                    println!("--- To-Do Stats ---");
+                    println!("Total:     {}", total);
+                    println!("Completed: {}", completed);
+                    println!("Pending:   {}", pending);
+                    println!("Progress:  {:.0}%", percent);
+                    if let Some(overdue) = overdue {
+                        println!("Overdue:   {}", overdue);
+                    }
+                    if let Some((minutes, unestimated)) = remaining {
+                        print!("Remaining: ≈ {} of work", format_minutes(minutes));
+                        if unestimated > 0 {
+                            print!(" (+{} pending without an estimate)", unestimated);
+                        }
+                        println!();
+                    }
+                    if let Some(tags) = &tags {
+                        let labels: Vec<String> = tags
+                            .iter()
+                            .map(|(tag, ..)| match tag {
+                                Some(tag) => format!("#{}", tag),
+                                None => "(none)".to_string(),
+                            })
+                            .collect();
+                        let width = labels
+                            .iter()
+                            .map(|l| l.chars().count())
+                            .fold("Tag".len(), usize::max);
+                        println!();
+                        println!("--- By Tag ---");
+                        println!(
+                            "{:<width$}  {:>5}  {:>9}  {:>7}",
+                            "Tag", "Total", "Completed", "Pending"
+                        );
+                        for (label, (_, total, completed)) in labels.iter().zip(tags) {
+                            println!(
+                                "{:<width$}  {:>5}  {:>9}  {:>7}",
+                                label,
+                                total,
+                                completed,
+                                total - completed
+                            );
+                        }
+                    }
+                }
+            }
+            // Stats are read-only, so there's nothing to save
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Move { id, to } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            let todo = list.todos.remove(index);
+            let new_index = to.saturating_sub(1).min(list.todos.len());
+            say!(
+                "↕️ Moved to-do {} to position {}: \"{}\"",
+                todo.id,
+                new_index + 1,
+                todo.task
+            );
+            list.todos.insert(new_index, todo);
+            list.renumber_order();
+        }
+
+        Commands::Renumber => {
+            let mut changed = 0;
+            let mut new_ids = BTreeMap::new();
+            for (index, todo) in list.todos.iter_mut().enumerate() {
+                let new_id = index as u32 + 1;
+                new_ids.insert(todo.id, new_id);
+                if todo.id != new_id {
+                    say!("🔢 {} -> {}: \"{}\"", todo.id, new_id, todo.task);
+                    todo.id = new_id;
+                    changed += 1;
+                }
+            }
+            // Dependencies follow the to-dos to their new IDs
+            for todo in &mut list.todos {
+                for dependency in &mut todo.depends_on {
+                    if let Some(&new_id) = new_ids.get(dependency) {
+                        *dependency = new_id;
+                    }
+                }
+            }
+
+            if changed == 0 {
+                say!("IDs are already in order, nothing to renumber.");
+                return Ok(ExitCode::SUCCESS);
+            }
+            // The counter carries on from where it was, so IDs that scripts or
+            // the undo history still remember from deleted to-dos aren't given
+            // to new ones later
+            list.fix_next_id();
+            list.renumber_order();
+            say!("Renumbered {} to-dos.", changed);
+        }
+
+        Commands::Import {
+            path,
+            merge,
+            skip_existing,
+        } => {
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+
+            if merge {
+                // Parse the file directly rather than with load_todos, which
+                // would move a damaged file aside; this one isn't ours to touch
+                let mut source = parse_todo_file(&contents, &path)
+                    .with_context(|| format!("{} is not a valid to-do file", path.display()))?;
+                let Some(incoming) = source.lists.remove(&list_name) else {
+                    let names: Vec<&str> = source.lists.keys().map(String::as_str).collect();
+                    bail!(
+                        "{} has no list called \"{}\" (it has: {})",
+                        path.display(),
+                        list_name,
+                        names.join(", ")
+                    );
+                };
+
+                let mut added = 0;
+                let mut skipped = 0;
+                let mut new_ids = BTreeMap::new();
+                let first_added = list.todos.len();
+                for mut todo in incoming.todos {
+                    // The same to-do (by ULID) is always skipped, so merging
+                    // twice doesn't duplicate anything
+                    let same_uid =
+                        !todo.uid.is_empty() && list.todos.iter().any(|t| t.uid == todo.uid);
+                    let wanted = todo.task.trim().to_lowercase();
+                    let same_task = skip_existing
+                        && list
+                            .todos
+                            .iter()
+                            .any(|t| t.task.trim().to_lowercase() == wanted);
+                    if same_uid || same_task {
+                        skipped += 1;
+                        continue;
+                    }
+                    let new_id = list.allocate_id();
+                    new_ids.insert(todo.id, new_id);
+                    todo.id = new_id;
+                    todo.order = new_id;
+                    list.todos.push(todo);
+                    added += 1;
+                }
+                // Dependencies are moved onto the new IDs; ones on to-dos that
+                // weren't merged have nothing to point at here, so they go
+                for todo in &mut list.todos[first_added..] {
+                    todo.depends_on = todo
+                        .depends_on
+                        .iter()
+                        .filter_map(|id| new_ids.get(id).copied())
+                        .collect();
+                }
+                say!(
+                    "📥 Merged {} to-dos from {} ({} skipped).",
+                    added,
+                    path.display(),
+                    skipped
+                );
+                if added == 0 {
+                    return Ok(ExitCode::SUCCESS);
+                }
+            } else {
+                let mut imported = 0;
+                for line in contents.lines() {
+                    let task = line.trim();
+                    if task.is_empty() {
+                        continue;
+                    }
+                    let new_todo = Todo::new(list.allocate_id(), task.to_string());
+                    list.todos.push(new_todo);
+                    imported += 1;
+                }
+                say!("📥 Imported {} to-dos from {}.", imported, path.display());
+            }
+        }
+
+        Commands::Export { format, out, group } => {
+            let mut buffer = Vec::new();
+            match format {
+                ExportFormat::Csv => write_csv(&mut buffer, &list.todos)?,
+                ExportFormat::Markdown => write_markdown(&mut buffer, &list.todos, group)?,
+            }
+
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, &buffer)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    say!(
+                        "📤 Exported {} to-dos to {}.",
+                        list.todos.len(),
+                        path.display()
+                    );
+                }
+                None => std::io::stdout()
+                    .write_all(&buffer)
+                    .context("Failed to write export to standard output")?,
+            }
+            // Exporting doesn't change the list, so there's nothing to save
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Edit {
+            id,
+            new_task,
+            due,
+            priority,
+            tags,
+            estimate,
+            depends_on,
+            interactive,
+        } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            list.check_dependencies(list.todos[index].id, &depends_on)?;
+            // Only the fields that were given are changed
+            let todo = &mut list.todos[index];
+            if interactive {
+                match edit_in_editor(todo)? {
+                    Some((task, note)) => {
+                        todo.task = clean_task(&task, max_task_length)?;
+                        todo.note = note;
+                    }
+                    None if due.is_none()
+                        && priority.is_none()
+                        && tags.is_empty()
+                        && estimate.is_none()
+                        && depends_on.is_empty() =>
+                    {
+                        say!("Nothing was changed.");
+                        return Ok(ExitCode::SUCCESS);
+                    }
+                    None => {}
+                }
+            }
+            if let Some(new_task) = new_task {
+                todo.task = clean_task(&new_task, max_task_length)?;
+            }
+            if let Some(due) = due {
+                todo.due = Some(due);
+            }
+            if let Some(priority) = priority {
+                todo.priority = priority;
+            }
+            if !tags.is_empty() {
+                todo.tags = tags;
+            }
+            if let Some(estimate) = estimate {
+                todo.estimate_minutes = Some(estimate);
+            }
+            if !depends_on.is_empty() {
+                todo.depends_on = depends_on;
+            }
+            say!("📝 Edited to-do {}: \"{}\"", todo.id, todo.task);
+        }
+
+        Commands::Rename { id, task } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            // Same checks as edit --new-task
+            let todo = &mut list.todos[index];
+            let old_task = std::mem::replace(&mut todo.task, clean_task(&task, max_task_length)?);
+            say!(
+                "📝 Renamed to-do {}: \"{}\" -> \"{}\"",
+                todo.id,
+                old_task,
+                todo.task
+            );
+        }
+
+        Commands::Snooze { id, duration } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            let todo = &mut list.todos[index];
+            let due = duration.after(todo.due.unwrap_or_else(Date::today));
+            todo.due = Some(due);
+            say!("😴 Snoozed to-do {}: now due {}.", todo.id, due);
+        }
+
+        Commands::Note { id, note } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            let todo = &mut list.todos[index];
+            if note.trim().is_empty() {
+                todo.note = None;
+                say!("🗒️ Removed the note from to-do {}.", todo.id);
+            } else {
+                todo.note = Some(note);
+                say!("🗒️ Updated the note on to-do {}.", todo.id);
+            }
+        }
+
+        // complete --undo-last takes back the most recent completion instead
+        Commands::Complete {
+            undo_last: true, ..
+        } => {
+            // Items completed before completion times were recorded can't
+            // be ordered, so they're never picked
+            let last = (0..list.todos.len())
+                .filter(|&index| list.todos[index].completed)
+                .filter_map(|index| Some((list.todos[index].completed_at?, index)))
+                .max_by_key(|(at, _)| *at);
+            let Some((_, index)) = last else {
+                say!("Nothing to reopen, no to-dos have a completion time.");
+                return Ok(ExitCode::SUCCESS);
+            };
+            reopen(&mut list, index, "Reopened");
+        }
+
+        Commands::Complete {
+            ids,
+            index,
+            all,
+            tag,
+            yes,
+            force,
+            undo_last: false,
+        } => {
+            // Work out which to-dos to complete before touching anything
+            let targets: Vec<usize> = if let Some(tag) = &tag {
+                let tagged: Vec<usize> = (0..list.todos.len())
+                    .filter(|&index| {
+                        let todo = &list.todos[index];
+                        !todo.completed && todo.tags.contains(tag)
+                    })
+                    .collect();
+                if tagged.is_empty() {
+                    say!(
+                        "Nothing to complete, no pending to-dos are tagged #{}.",
+                        tag
+                    );
+                    return Ok(ExitCode::SUCCESS);
+                }
+                tagged
+            } else if all {
+                let pending: Vec<usize> = (0..list.todos.len())
+                    .filter(|&index| !list.todos[index].completed)
+                    .collect();
+                if pending.is_empty() {
+                    say!("Nothing to complete, every to-do is already done.");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                let prompt = format!("Complete all {} pending to-dos?", pending.len());
+                if !yes && !confirm(&prompt)? {
+                    say!("Nothing was completed.");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                pending
+            } else {
+                let mut found = Vec::new();
+                let shown = shown_ids(&list.todos, config.default_sort);
+                for id in ids {
+                    let id = if index {
+                        let Some(id) = id_at_position(&shown, &id) else {
+                            missing = true;
+                            continue;
+                        };
+                        id
+                    } else {
+                        id
+                    };
+                    match find_todo(&list.todos, &id) {
+                        Some(index) if !found.contains(&index) => found.push(index),
+                        Some(_) => {}
+                        None => missing = true,
+                    }
+                }
+                if found.is_empty() {
+                    // None of the to-dos were found, so nothing changed
+                    return Ok(ExitCode::FAILURE);
+                }
+                found
+            };
+
+            // A to-do can't be done before the ones it depends on, unless
+            // those are being completed in this same command
+            let mut targets = targets;
+            if !force {
+                let completing: BTreeSet<u32> =
+                    targets.iter().map(|&index| list.todos[index].id).collect();
+                targets.retain(|&index| {
+                    let reason = list.blocked_reason(&list.todos[index], &completing);
+                    if let Some(reason) = &reason {
+                        eprintln!("Error: {}. Use --force to complete it anyway.", reason);
+                        missing = true;
+                    }
+                    reason.is_none()
+                });
+                if targets.is_empty() {
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+
+            // New occurrences of recurring to-dos go on the end of the list,
+            // so the positions worked out above stay valid
+            let count = targets.len();
+            for index in targets {
+                if !all && tag.is_none() {
+                    let todo = &list.todos[index];
+                    say!("🎉 Completed to-do {}: \"{}\"", todo.id, todo.task);
+                }
+                complete(&mut list, index);
+            }
+            if all {
+                say!("🎉 Completed all {} pending to-dos.", count);
+            } else if let Some(tag) = &tag {
+                say!("🎉 Completed {} to-dos tagged #{}.", count, tag);
+            }
+        }
+
+        Commands::Pin { id } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            let todo = &mut list.todos[index];
+            todo.pinned = true;
+            say!("📌 Pinned to-do {}: \"{}\"", todo.id, todo.task);
+        }
+
+        Commands::Unpin { id } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            let todo = &mut list.todos[index];
+            todo.pinned = false;
+            say!("Unpinned to-do {}: \"{}\"", todo.id, todo.task);
+        }
+
+        Commands::Undo => {
+            let entry = store
+                .undo_path()
+                .and_then(|undo_file| undo::last(&undo_file, &list_name));
+            let Some(entry) = entry else {
+                say!("Nothing to undo.");
+                return Ok(ExitCode::SUCCESS);
+            };
+            list = entry.undo(list);
+            say!("↩️ Undid \"{}\" (from {}).", entry.command, entry.at);
+        }
+
+        Commands::Uncomplete { id } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            reopen(&mut list, index, "Reopened");
+        }
+
+        Commands::Toggle { id, force } => {
+            let Some(index) = find_todo(&list.todos, &id) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            // Completing this way is held up by dependencies just like `complete`
+            if !list.todos[index].completed
+                && !force
+                && let Some(reason) = list.blocked_reason(&list.todos[index], &BTreeSet::new())
+            {
+                eprintln!("Error: {}. Use --force to complete it anyway.", reason);
+                return Ok(ExitCode::FAILURE);
+            }
+            if list.todos[index].completed {
+                reopen(&mut list, index, "Marked incomplete");
+            } else {
+                let todo = &list.todos[index];
+                say!("✅ Marked complete to-do {}: \"{}\"", todo.id, todo.task);
+                complete(&mut list, index);
+            }
+        }
+
+        Commands::Delete {
+            ids,
+            index,
+            all_completed,
+            tag,
+            yes,
+        } => {
+            // Work out what would be deleted before touching anything
+            let mut found = Vec::new();
+            if all_completed {
+                found.extend(list.todos.iter().filter(|t| t.completed).map(|t| t.id));
+                if found.is_empty() {
+                    say!("Nothing to delete, no to-dos are completed.");
+                    return Ok(ExitCode::SUCCESS);
+                }
+            }
+            if let Some(tag) = &tag {
+                found.extend(
+                    list.todos
+                        .iter()
+                        .filter(|t| t.tags.contains(tag))
+                        .map(|t| t.id),
+                );
+                if found.is_empty() {
+                    say!("Nothing to delete, no to-dos are tagged #{}.", tag);
+                    return Ok(ExitCode::SUCCESS);
+                }
+            }
+            let shown = shown_ids(&list.todos, config.default_sort);
+            for id in ids {
+                let id = if index {
+                    let Some(id) = id_at_position(&shown, &id) else {
+                        missing = true;
+                        continue;
+                    };
+                    id
+                } else {
+                    id
+                };
+                match find_todo(&list.todos, &id) {
+                    Some(index) if !found.contains(&list.todos[index].id) => {
+                        found.push(list.todos[index].id)
+                    }
+                    Some(_) => {}
+                    None => missing = true,
+                }
+            }
+            if found.is_empty() {
+                return Ok(ExitCode::FAILURE);
+            }
+
+            if !yes {
+                for todo in list.todos.iter().filter(|t| found.contains(&t.id)) {
+                    println!("{}", format_todo(todo));
+                }
+                let prompt = if found.len() == 1 {
+                    "Delete this to-do?".to_string()
+                } else {
+                    format!("Delete these {} to-dos?", found.len())
+                };
+                if !confirm(&prompt)? {
+                    say!("Nothing was deleted.");
+                    return Ok(ExitCode::SUCCESS);
+                }
+            }
+
+            list.todos.retain(|t| !found.contains(&t.id));
+            if all_completed {
+                say!("🗑️ Deleted {} completed to-dos.", found.len());
+            } else if let Some(tag) = &tag {
+                say!("🗑️ Deleted {} to-dos tagged #{}.", found.len(), tag);
+            } else {
+                for id in found {
+                    say!("🗑️ Deleted to-do with ID {}.", id);
+                }
+            }
+        }
+
+        Commands::Watch => {
+            let defaults = ListStyle::default();
+            let style = ListStyle {
+                done_marker: config.done_marker.unwrap_or(defaults.done_marker),
+                pending_marker: config.pending_marker.unwrap_or(defaults.pending_marker),
+                date_format: config.date_format,
+                blocked: BTreeSet::new(),
+            };
+            let color = config.color != Some(false) && use_color();
+            watch(&todo_file, store.as_ref(), &list_name, &style, color)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        Commands::Tui => {
+            if !tui::run(&mut list)? {
+                // Nothing was toggled or deleted, so leave the file alone
+                return Ok(ExitCode::SUCCESS);
+            }
+        }
+
+        Commands::Reset { yes } => {
+            if list.todos.is_empty() {
+                say!("The list is already empty, nothing to reset.");
+                return Ok(ExitCode::SUCCESS);
+            }
+            if !yes && !confirm("This will delete ALL to-dos. Continue?")? {
+                say!("Nothing was deleted.");
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let count = list.todos.len();
+            list.todos.clear();
+            match store.backup_path() {
+                Some(backup) => say!(
+                    "🧨 Deleted all {} to-dos. The old list was backed up to {}.",
+                    count,
+                    backup.display()
+                ),
+                None => say!("🧨 Deleted all {} to-dos.", count),
+            }
+        }
+
+        Commands::Restore { yes } => {
+            let Some(backup) = store.backup_path() else {
+                bail!(
+                    "{} has no backups to restore (only JSON and YAML files do); use `undo` to go back instead",
+                    todo_file.display()
+                );
+            };
+            let contents = match std::fs::read_to_string(&backup) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => bail!(
+                    "There is no backup to restore ({} doesn't exist yet; one is made every time the list is saved)",
+                    backup.display()
+                ),
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read {}", backup.display()));
+                }
+            };
+            // Like import --merge, parse it directly: a damaged backup should be
+            // reported, not moved aside like the to-do file would be. Backups
+            // are copies of the to-do file, so they're in the same format.
+            let mut file = parse_todo_file(&contents, &todo_file)
+                .with_context(|| format!("{} is not a valid to-do file", backup.display()))?;
+            let Some(restored) = file.lists.remove(&list_name) else {
+                bail!(
+                    "The backup {} has no list called \"{}\"",
+                    backup.display(),
+                    list_name
+                );
+            };
+
+            let prompt = format!(
+                "Replace the list ({} to-dos) with the backup from {} ({} to-dos)?",
+                list.todos.len(),
+                backup.display(),
+                restored.todos.len()
+            );
+            if !yes && !confirm(&prompt)? {
+                say!("Nothing was restored.");
+                return Ok(ExitCode::SUCCESS);
+            }
+            // Saving backs up the list being replaced, so a restore can itself
+            // be undone, or restored away again
+            say!(
+                "♻️ Restored {} to-dos from {}.",
+                restored.todos.len(),
+                backup.display()
+            );
+            list = restored;
+        }
+
+        Commands::Doctor {
+            fix,
+            completed_older_than,
+        } => {
+            let problems = doctor::diagnose(&list, Date::today(), completed_older_than);
+            if problems.is_empty() {
+                println!("No problems found.");
+                return Ok(ExitCode::SUCCESS);
+            }
+            println!("Found {} problems:", problems.len());
+            for problem in &problems {
+                println!("  - {}", problem.message);
+                println!("    {}", problem.suggestion);
+            }
+            if !fix {
+                if problems.iter().any(|p| p.fixable) {
+                    say!("Run `doctor --fix` to repair the ones it can.");
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            let repairs = doctor::repair(&mut list);
+            if repairs.is_empty() {
+                say!("None of these can be repaired automatically.");
+                return Ok(ExitCode::SUCCESS);
+            }
+            for repair in repairs {
+                say!("🔧 {}.", repair);
+            }
+        }
+
+        Commands::Completions { .. } => unreachable!("handled before loading the to-do file"),
+
+        Commands::Purge { older_than_days } => {
+            let cutoff = Timestamp::now().days_before(older_than_days);
+            // Completed items without a completion time are kept, since
+            // there's no telling how old they are
+            let (purged, kept): (Vec<Todo>, Vec<Todo>) = list
+                .todos
+                .drain(..)
+                .partition(|t| t.completed && t.completed_at.is_some_and(|at| at < cutoff));
+            list.todos = kept;
+
+            if purged.is_empty() {
+                say!(
+                    "Nothing to purge, no to-dos were completed more than {} days ago.",
+                    older_than_days
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            let ids: Vec<String> = purged.iter().map(|t| t.id.to_string()).collect();
+            say!(
+                "🧹 Purged {} completed to-dos (IDs {}).",
+                purged.len(),
+                ids.join(", ")
+            );
+        }
+
+        Commands::ClearCompleted => {
+            let initial_len = list.todos.len();
+            list.todos.retain(|t| !t.completed);
+            let removed = initial_len - list.todos.len();
+
+            if removed == 0 {
+                say!("Nothing to clear, no to-dos are completed.");
+                // Nothing changed, so leave the file alone
+                return Ok(ExitCode::SUCCESS);
+            }
+            say!("🧹 Removed {} completed to-dos.", removed);
+        }
+    }
+
+    // Save the potentially modified list of todos back to the file,
+    // unless this was only a preview. A command that left the list as it was
+    // (say, renaming a to-do to the text it already had) doesn't save, so a
+    // hand-formatted file isn't rewritten and undo doesn't get an empty step.
+    let unchanged = list == before && !is_undo;
+    if cli.dry_run {
+        println!(
+            "{}Dry run: the changes above were not saved.",
+            emoji("🔍 ", "")
+        );
+    } else if !unchanged {
+        let _writing = writing.unwrap_or_else(DeferInterrupts::new);
+        store::save_changes(store.as_ref(), &list_name, &before, &list)?;
+
+        // Keep the undo history in step with the file. It's only a convenience,
+        // so a problem with it shouldn't fail a change that was already saved.
+        if let Some(undo_file) = store.undo_path() {
+            let updated = if is_undo {
+                undo::remove_last(&undo_file, &list_name)
+            } else {
+                undo::record(
+                    &undo_file,
+                    UndoEntry::new(list_name.clone(), command_line(), &before, &list),
+                )
+            };
+            if let Err(e) = updated {
+                eprintln!("Warning: could not update the undo history: {:#}", e);
+            }
+        }
+
+        if interrupt::received() {
+            eprintln!("Interrupted, but only after your changes were saved.");
+            // 128 + SIGINT, what shells report for a program stopped by Ctrl-C
+            return Ok(ExitCode::from(130));
+        }
+    }
+
+    if missing {
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,264 @@
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::path::Path;
+use std::ptr;
+
+use anyhow::{Context, Result, anyhow};
+
+// Just enough of SQLite for the SQLite store in store.rs. There's no SQLite
+// crate among the dependencies, so this talks to the system's libsqlite3
+// directly, the same way interrupt.rs talks to the C library.
+mod sys {
+    use std::ffi::{c_char, c_int, c_void};
+
+    // Opaque handles for a database connection and a prepared statement
+    #[repr(C)]
+    pub struct Sqlite3 {
+        _private: [u8; 0],
+    }
+    #[repr(C)]
+    pub struct Stmt {
+        _private: [u8; 0],
+    }
+
+    pub const SQLITE_OK: c_int = 0;
+    pub const SQLITE_ROW: c_int = 100;
+    pub const SQLITE_DONE: c_int = 101;
+    pub const SQLITE_OPEN_READWRITE: c_int = 0x2;
+    pub const SQLITE_OPEN_CREATE: c_int = 0x4;
+    // The "destructor" that makes SQLite copy bound text straight away
+    pub const SQLITE_TRANSIENT: usize = usize::MAX;
+
+    #[link(name = "sqlite3")]
+    unsafe extern "C" {
+        pub fn sqlite3_open_v2(
+            filename: *const c_char,
+            db: *mut *mut Sqlite3,
+            flags: c_int,
+            vfs: *const c_char,
+        ) -> c_int;
+        pub fn sqlite3_close(db: *mut Sqlite3) -> c_int;
+        pub fn sqlite3_errmsg(db: *mut Sqlite3) -> *const c_char;
+        pub fn sqlite3_busy_timeout(db: *mut Sqlite3, ms: c_int) -> c_int;
+        pub fn sqlite3_exec(
+            db: *mut Sqlite3,
+            sql: *const c_char,
+            callback: *const c_void,
+            arg: *mut c_void,
+            errmsg: *mut *mut c_char,
+        ) -> c_int;
+        pub fn sqlite3_prepare_v2(
+            db: *mut Sqlite3,
+            sql: *const c_char,
+            bytes: c_int,
+            stmt: *mut *mut Stmt,
+            tail: *mut *const c_char,
+        ) -> c_int;
+        pub fn sqlite3_bind_text(
+            stmt: *mut Stmt,
+            index: c_int,
+            text: *const c_char,
+            bytes: c_int,
+            destructor: usize,
+        ) -> c_int;
+        pub fn sqlite3_bind_int64(stmt: *mut Stmt, index: c_int, value: i64) -> c_int;
+        pub fn sqlite3_step(stmt: *mut Stmt) -> c_int;
+        pub fn sqlite3_column_text(stmt: *mut Stmt, column: c_int) -> *const u8;
+        pub fn sqlite3_column_bytes(stmt: *mut Stmt, column: c_int) -> c_int;
+        pub fn sqlite3_column_int64(stmt: *mut Stmt, column: c_int) -> i64;
+        pub fn sqlite3_reset(stmt: *mut Stmt) -> c_int;
+        pub fn sqlite3_clear_bindings(stmt: *mut Stmt) -> c_int;
+        pub fn sqlite3_finalize(stmt: *mut Stmt) -> c_int;
+    }
+}
+
+// An open database. It's closed when this is dropped, which also rolls back
+// a transaction that was never committed.
+pub struct Connection {
+    db: *mut sys::Sqlite3,
+}
+
+// A value for one of a statement's `?` placeholders.
+pub enum Param<'a> {
+    Text(&'a str),
+    Int(i64),
+}
+
+impl Connection {
+    // Opens the database at `path`, creating the file if it doesn't exist.
+    pub fn open(path: &Path) -> Result<Connection> {
+        let name = CString::new(path.as_os_str().as_encoded_bytes())
+            .context("The file name contains a NUL byte")?;
+        let mut db = ptr::null_mut();
+        // SAFETY: `name` is a valid C string, and `db` is only used if set
+        let status = unsafe {
+            sys::sqlite3_open_v2(
+                name.as_ptr(),
+                &mut db,
+                sys::SQLITE_OPEN_READWRITE | sys::SQLITE_OPEN_CREATE,
+                ptr::null(),
+            )
+        };
+        // Even a failed open usually hands back a connection, with the reason
+        let connection = Connection { db };
+        if db.is_null() {
+            return Err(anyhow!("Failed to open {} (out of memory)", path.display()));
+        }
+        if status != sys::SQLITE_OK {
+            return Err(connection.error()).context(format!("Failed to open {}", path.display()));
+        }
+        // Wait a while for another program that has the database open, such as
+        // the sqlite3 shell, instead of failing straight away
+        // SAFETY: `db` is an open connection
+        unsafe { sys::sqlite3_busy_timeout(db, 5000) };
+        Ok(connection)
+    }
+
+    // Runs one or more statements that take no parameters and return no rows.
+    pub fn execute(&self, sql: &str) -> Result<()> {
+        let sql = CString::new(sql)?;
+        // SAFETY: `sql` is a valid C string; no callback or error string is wanted
+        let status = unsafe {
+            sys::sqlite3_exec(
+                self.db,
+                sql.as_ptr(),
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        if status != sys::SQLITE_OK {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    // Compiles one statement, to be run as many times as needed.
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        let mut stmt = ptr::null_mut();
+        // SAFETY: the length passed is the length of `sql`
+        let status = unsafe {
+            sys::sqlite3_prepare_v2(
+                self.db,
+                sql.as_ptr().cast::<c_char>(),
+                sql.len() as c_int,
+                &mut stmt,
+                ptr::null_mut(),
+            )
+        };
+        if status != sys::SQLITE_OK {
+            return Err(self.error());
+        }
+        Ok(Statement {
+            connection: self,
+            stmt,
+        })
+    }
+
+    // The error message SQLite has for the last call that failed.
+    fn error(&self) -> anyhow::Error {
+        // SAFETY: SQLite always returns a valid C string here, which it owns
+        let message = unsafe { CStr::from_ptr(sys::sqlite3_errmsg(self.db)) };
+        anyhow!("SQLite error: {}", message.to_string_lossy())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // SAFETY: every Statement borrows the connection, so none is left
+        unsafe { sys::sqlite3_close(self.db) };
+    }
+}
+
+pub struct Statement<'a> {
+    connection: &'a Connection,
+    stmt: *mut sys::Stmt,
+}
+
+// The current row of a query's results.
+pub struct Row {
+    stmt: *mut sys::Stmt,
+}
+
+impl Statement<'_> {
+    // Runs the statement with `params`, ignoring any rows it returns.
+    pub fn execute(&mut self, params: &[Param]) -> Result<()> {
+        self.query(params, |_| Ok(()))
+    }
+
+    // Runs the statement with `params` and calls `each` on every row.
+    pub fn query(
+        &mut self,
+        params: &[Param],
+        mut each: impl FnMut(&Row) -> Result<()>,
+    ) -> Result<()> {
+        let result = self.bind(params).and_then(|()| {
+            loop {
+                // SAFETY: the statement is prepared and its parameters bound
+                match unsafe { sys::sqlite3_step(self.stmt) } {
+                    sys::SQLITE_ROW => each(&Row { stmt: self.stmt })?,
+                    sys::SQLITE_DONE => break Ok(()),
+                    _ => break Err(self.connection.error()),
+                }
+            }
+        });
+        // Ready to run again, without holding on to the bound text
+        // SAFETY: resetting a prepared statement is always allowed
+        unsafe {
+            sys::sqlite3_reset(self.stmt);
+            sys::sqlite3_clear_bindings(self.stmt);
+        }
+        result
+    }
+
+    fn bind(&mut self, params: &[Param]) -> Result<()> {
+        for (index, param) in params.iter().enumerate() {
+            // Placeholders are numbered from 1
+            let index = index as c_int + 1;
+            // SAFETY: SQLITE_TRANSIENT makes SQLite copy the text before returning
+            let status = unsafe {
+                match param {
+                    Param::Text(text) => sys::sqlite3_bind_text(
+                        self.stmt,
+                        index,
+                        text.as_ptr().cast::<c_char>(),
+                        text.len() as c_int,
+                        sys::SQLITE_TRANSIENT,
+                    ),
+                    Param::Int(value) => sys::sqlite3_bind_int64(self.stmt, index, *value),
+                }
+            };
+            if status != sys::SQLITE_OK {
+                return Err(self.connection.error());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the statement isn't used again
+        unsafe { sys::sqlite3_finalize(self.stmt) };
+    }
+}
+
+impl Row {
+    // The text in column `column` (counting from 0), empty if it's NULL.
+    pub fn text(&self, column: c_int) -> String {
+        // SAFETY: the pointer and length stay valid until the next step, and
+        // the bytes are copied out before then
+        unsafe {
+            let text = sys::sqlite3_column_text(self.stmt, column);
+            if text.is_null() {
+                return String::new();
+            }
+            let length = sys::sqlite3_column_bytes(self.stmt, column) as usize;
+            String::from_utf8_lossy(std::slice::from_raw_parts(text, length)).into_owned()
+        }
+    }
+
+    pub fn int(&self, column: c_int) -> i64 {
+        // SAFETY: reading a column of the current row
+        unsafe { sys::sqlite3_column_int64(self.stmt, column) }
+    }
+}
@@ -0,0 +1,768 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "sqlite")]
+use crate::sqlite::{Connection, Param};
+use crate::{
+    Todo, TodoList, backup_path, companion_path, load_todo_file, load_todos, save_todos,
+    with_suffix, write_todo_file, yaml,
+};
+
+// Where the lists of to-dos are kept. Commands load a whole list, change it in
+// memory and hand it back through save_changes, which passes a change to a
+// single to-do on as `add`, `update` or `remove`. A backend only has to read
+// and write lists by name; the single-to-do methods fall back on doing that,
+// and backends with a row per to-do replace them with something quicker.
+// Other backends can be added by implementing this trait, without touching
+// the commands. (There's no separate method for all of a list's to-dos,
+// since `load` already returns them along with the next free ID.)
+pub trait Store {
+    // The named list, or an empty one if it doesn't exist yet.
+    fn load(&self, list: &str) -> Result<TodoList>;
+    // Replaces the named list, leaving any other lists as they are.
+    fn save(&self, list: &str, todos: &TodoList) -> Result<()>;
+
+    // Adds a new to-do at the end of the list. It has its ID and ULID already.
+    fn add(&self, list: &str, todo: &Todo) -> Result<()> {
+        let mut todos = self.load(list)?;
+        todos.next_id = todos.next_id.max(todo.id + 1);
+        todos.todos.push(todo.clone());
+        self.save(list, &todos)
+    }
+
+    // Replaces the to-do with the same ULID.
+    fn update(&self, list: &str, todo: &Todo) -> Result<()> {
+        let mut todos = self.load(list)?;
+        for old in todos.todos.iter_mut().filter(|t| t.uid == todo.uid) {
+            *old = todo.clone();
+        }
+        self.save(list, &todos)
+    }
+
+    // Removes the to-do with this ULID.
+    fn remove(&self, list: &str, uid: &str) -> Result<()> {
+        let mut todos = self.load(list)?;
+        todos.todos.retain(|t| t.uid != uid);
+        self.save(list, &todos)
+    }
+
+    // Where the history for `undo` is kept, if anywhere. The history is
+    // always plain JSON, so a file that isn't gets a name of its own.
+    fn undo_path(&self) -> Option<PathBuf>;
+
+    // The backup `restore` goes back to, for backends that make one on every
+    // save. It may not exist yet.
+    fn backup_path(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+// Saves `after` in place of `before`, the list as it was loaded. A change to
+// a single to-do goes through add, update or remove, so a backend with a row
+// per to-do only writes that row; anything more saves the whole list.
+pub fn save_changes(
+    store: &dyn Store,
+    list: &str,
+    before: &TodoList,
+    after: &TodoList,
+) -> Result<()> {
+    let mut after = after.clone();
+    after.fix_uids();
+    let (changed, deleted) = changes(before, &after);
+    let same_next_id = after.next_id == before.next_id;
+    match (changed.as_slice(), deleted.as_slice()) {
+        ([todo], []) => {
+            let is_new = !before.todos.iter().any(|t| t.uid == todo.uid);
+            let is_last = after.todos.last().is_some_and(|t| t.uid == todo.uid);
+            if !is_new && same_next_id {
+                store.update(list, todo)
+            } else if is_new && is_last && after.next_id == before.next_id.max(todo.id + 1) {
+                store.add(list, todo)
+            } else {
+                store.save(list, &after)
+            }
+        }
+        ([], [uid]) if same_next_id => store.remove(list, uid),
+        _ => store.save(list, &after),
+    }
+}
+
+// Lists kept in a JSON to-do file such as todos.json (see load_todos and
+// save_todos for the details of the format, backups and atomic saving).
+pub struct JsonFileStore {
+    path: PathBuf,
+    // Wait for every save to reach the disk (see --sync)
+    sync: bool,
+}
+
+impl JsonFileStore {
+    pub fn new(path: PathBuf, sync: bool) -> JsonFileStore {
+        JsonFileStore { path, sync }
+    }
+}
+
+impl JsonFileStore {
+    // Reads the file, changes one list and writes it back. The whole file is
+    // rewritten either way, but this saves reading it twice, as loading the
+    // list and then saving it would.
+    fn change(&self, list: &str, change: impl FnOnce(&mut TodoList)) -> Result<()> {
+        let save = || {
+            let mut file = load_todo_file(&self.path)?;
+            let todos = file
+                .lists
+                .entry(list.to_string())
+                .or_insert_with(|| TodoList::new(Vec::new()));
+            change(todos);
+            todos.fix_uids();
+            write_todo_file(&self.path, &file, self.sync)
+        };
+        save().with_context(|| format!("Could not save your to-dos ({})", self.path.display()))
+    }
+}
+
+impl Store for JsonFileStore {
+    fn load(&self, list: &str) -> Result<TodoList> {
+        load_todos(&self.path, list)
+    }
+
+    fn save(&self, list: &str, todos: &TodoList) -> Result<()> {
+        save_todos(&self.path, list, todos, self.sync)
+    }
+
+    fn add(&self, list: &str, todo: &Todo) -> Result<()> {
+        self.change(list, |todos| {
+            todos.next_id = todos.next_id.max(todo.id + 1);
+            todos.todos.push(todo.clone());
+        })
+    }
+
+    fn update(&self, list: &str, todo: &Todo) -> Result<()> {
+        self.change(list, |todos| {
+            for old in todos.todos.iter_mut().filter(|t| t.uid == todo.uid) {
+                *old = todo.clone();
+            }
+        })
+    }
+
+    fn remove(&self, list: &str, uid: &str) -> Result<()> {
+        self.change(list, |todos| todos.todos.retain(|t| t.uid != uid))
+    }
+
+    // todos.json -> todos.undo.json, but todos.yaml -> todos.yaml.undo.json
+    // so it can't be mistaken for the history of a todos.json next to it
+    fn undo_path(&self) -> Option<PathBuf> {
+        if yaml::is_yaml(&self.path) {
+            return Some(with_suffix(&self.path, ".undo.json"));
+        }
+        Some(companion_path(&self.path, "undo"))
+    }
+
+    // Saving copies the current file to backup 1 before replacing it
+    fn backup_path(&self) -> Option<PathBuf> {
+        Some(backup_path(&self.path, 1))
+    }
+}
+
+// Lists kept in a JSON Lines file such as todos.jsonl: a log with one record
+// per line, which is only ever appended to. Adding a to-do writes a single
+// line instead of the whole file, which matters for very long lists. Reading
+// replays the log: a later record for the same to-do (matched by ULID)
+// replaces the earlier one, and a `deleted` record removes it.
+//
+//   {"list":"default","todo":{"id":1,"task":"Buy milk",...}}
+//   {"list":"default","next_id":2}
+//   {"list":"default","deleted":"01JQ3Z7X5V9K2M8R4T6W0Y1B3C"}
+pub struct JsonLinesStore {
+    path: PathBuf,
+    sync: bool,
+    // The last list loaded or saved, as it stands in the file, so saving it
+    // again doesn't have to replay the whole log to find what changed. The
+    // file is locked from load to save, so nothing else can change it between.
+    known: RefCell<Option<(String, TodoList)>>,
+}
+
+// One line of a JSON Lines file. Exactly one of the optional fields is set.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    list: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    todo: Option<Todo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deleted: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    next_id: Option<u32>,
+}
+
+impl Record {
+    fn new(list: &str) -> Record {
+        Record {
+            list: list.to_string(),
+            todo: None,
+            deleted: None,
+            next_id: None,
+        }
+    }
+
+    fn todo(list: &str, todo: &Todo) -> Record {
+        Record {
+            todo: Some(todo.clone()),
+            ..Record::new(list)
+        }
+    }
+
+    fn deleted(list: &str, uid: &str) -> Record {
+        Record {
+            deleted: Some(uid.to_string()),
+            ..Record::new(list)
+        }
+    }
+}
+
+impl JsonLinesStore {
+    pub fn new(path: PathBuf, sync: bool) -> JsonLinesStore {
+        JsonLinesStore {
+            path,
+            sync,
+            known: RefCell::new(None),
+        }
+    }
+
+    // Adds records to the end of the log, all in one write so they land
+    // together. If the file ends in a half-written line, they start on a
+    // fresh line after it.
+    fn append(&self, records: &[Record]) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut text = String::new();
+        for record in records {
+            text.push_str(
+                &serde_json::to_string(record).context("Failed to serialize a to-do record")?,
+            );
+            text.push('\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)
+            .context("Failed to open the todo file for appending")?;
+        if !ends_with_newline(&mut file)? {
+            text.insert(0, '\n');
+        }
+        file.write_all(text.as_bytes())
+            .context("Failed to append to the todo file")?;
+        if self.sync {
+            file.sync_all()
+                .context("Failed to flush the todo file to disk")?;
+        }
+        Ok(())
+    }
+
+    // Appends the record for a single change. The list remembered from
+    // loading no longer matches the file, so it's forgotten.
+    fn append_one(&self, record: Record) -> Result<()> {
+        self.known.borrow_mut().take();
+        self.append(&[record])
+            .with_context(|| format!("Could not save your to-dos ({})", self.path.display()))
+    }
+
+    // Replays the log for one list. A line that can't be read, most likely
+    // the last one after a crash mid-write, is skipped with a warning rather
+    // than losing the whole list. `warn` is off when saving, since loading
+    // has already warned about the same lines.
+    fn replay(&self, list: &str, warn: bool) -> Result<TodoList> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()));
+            }
+        };
+
+        let mut todos: Vec<Option<Todo>> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        let mut next_id = 0;
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Record = match serde_json::from_str(line) {
+                Ok(record) => record,
+                Err(e) => {
+                    if warn {
+                        eprintln!(
+                            "Warning: skipping line {} of {}, it could not be read ({}).",
+                            index + 1,
+                            self.path.display(),
+                            e
+                        );
+                    }
+                    continue;
+                }
+            };
+            if record.list != list {
+                continue;
+            }
+            if let Some(todo) = record.todo {
+                match positions.get(&todo.uid) {
+                    Some(&position) => todos[position] = Some(todo),
+                    None => {
+                        positions.insert(todo.uid.clone(), todos.len());
+                        todos.push(Some(todo));
+                    }
+                }
+            }
+            if let Some(uid) = record.deleted
+                && let Some(position) = positions.remove(&uid)
+            {
+                todos[position] = None;
+            }
+            if let Some(id) = record.next_id {
+                next_id = id;
+            }
+        }
+
+        let mut list = TodoList {
+            next_id,
+            todos: todos.into_iter().flatten().collect(),
+        };
+        list.fix_next_id();
+        list.fix_order();
+        Ok(list)
+    }
+}
+
+impl Store for JsonLinesStore {
+    fn load(&self, list: &str) -> Result<TodoList> {
+        let todos = self
+            .replay(list, true)
+            .with_context(|| format!("Could not load your to-dos ({})", self.path.display()))?;
+        *self.known.borrow_mut() = Some((list.to_string(), todos.clone()));
+        Ok(todos)
+    }
+
+    // Appends records for only what changed since the list was last saved.
+    fn save(&self, list: &str, todos: &TodoList) -> Result<()> {
+        let save = || -> Result<()> {
+            let known = self.known.borrow_mut().take();
+            let saved = match known {
+                Some((name, saved)) if name == list => saved,
+                _ => self.replay(list, false)?,
+            };
+            let mut todos = todos.clone();
+            todos.fix_uids();
+
+            let (changed, deleted) = changes(&saved, &todos);
+            let mut records = Vec::new();
+            for todo in changed {
+                records.push(Record::todo(list, todo));
+            }
+            for uid in deleted {
+                records.push(Record::deleted(list, uid));
+            }
+            if todos.next_id != saved.next_id {
+                records.push(Record {
+                    next_id: Some(todos.next_id),
+                    ..Record::new(list)
+                });
+            }
+            self.append(&records)?;
+            *self.known.borrow_mut() = Some((list.to_string(), todos));
+            Ok(())
+        };
+        save().with_context(|| format!("Could not save your to-dos ({})", self.path.display()))
+    }
+
+    // A new to-do's ID is never below next_id, and replaying the log moves
+    // next_id past it, so the to-do's own record is all that needs writing.
+    fn add(&self, list: &str, todo: &Todo) -> Result<()> {
+        self.append_one(Record::todo(list, todo))
+    }
+
+    fn update(&self, list: &str, todo: &Todo) -> Result<()> {
+        self.append_one(Record::todo(list, todo))
+    }
+
+    fn remove(&self, list: &str, uid: &str) -> Result<()> {
+        self.append_one(Record::deleted(list, uid))
+    }
+
+    // todos.jsonl -> todos.jsonl.undo.json
+    fn undo_path(&self) -> Option<PathBuf> {
+        Some(with_suffix(&self.path, ".undo.json"))
+    }
+}
+
+// What `save` has to write to turn the list as stored (`saved`) into `todos`:
+// the to-dos that are new or changed, and the ULIDs of the ones that are gone.
+fn changes<'a>(saved: &'a TodoList, todos: &'a TodoList) -> (Vec<&'a Todo>, Vec<&'a str>) {
+    let by_uid: HashMap<&str, &Todo> = saved.todos.iter().map(|t| (t.uid.as_str(), t)).collect();
+    let uids: HashSet<&str> = todos.todos.iter().map(|t| t.uid.as_str()).collect();
+    let changed = todos
+        .todos
+        .iter()
+        .filter(|todo| by_uid.get(todo.uid.as_str()) != Some(todo))
+        .collect();
+    let deleted = saved
+        .todos
+        .iter()
+        .map(|t| t.uid.as_str())
+        .filter(|uid| !uids.contains(uid))
+        .collect();
+    (changed, deleted)
+}
+
+// Lists kept in an SQLite database such as todos.db, one row per to-do, so
+// a save only touches the rows that changed. Each row holds the to-do as JSON,
+// which keeps the tables the same as fields are added to to-dos:
+//
+//   lists (name TEXT PRIMARY KEY, next_id INTEGER)
+//   todos (list TEXT, uid TEXT, todo TEXT, PRIMARY KEY (list, uid))
+//
+// To-dos come back in the order they were first added, as with JSON Lines.
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    path: PathBuf,
+    sync: bool,
+    // As for JsonLinesStore: the list as it stands in the database
+    known: RefCell<Option<(String, TodoList)>>,
+}
+
+#[cfg(feature = "sqlite")]
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS lists (name TEXT PRIMARY KEY, next_id INTEGER NOT NULL);
+    CREATE TABLE IF NOT EXISTS todos (
+        list TEXT NOT NULL,
+        uid TEXT NOT NULL,
+        todo TEXT NOT NULL,
+        PRIMARY KEY (list, uid)
+    );
+";
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    pub fn new(path: PathBuf, sync: bool) -> SqliteStore {
+        SqliteStore {
+            path,
+            sync,
+            known: RefCell::new(None),
+        }
+    }
+
+    fn connect(&self) -> Result<Connection> {
+        let connection = Connection::open(&self.path)?;
+        connection.execute(SQLITE_SCHEMA)?;
+        // Without --sync a power cut may lose the last change, but SQLite's
+        // journal still keeps the database itself from being damaged
+        connection.execute(if self.sync {
+            "PRAGMA synchronous = FULL"
+        } else {
+            "PRAGMA synchronous = NORMAL"
+        })?;
+        Ok(connection)
+    }
+
+    // Makes a change to single rows in one transaction. The list remembered
+    // from loading no longer matches the database, so it's forgotten.
+    fn write_rows(&self, change: impl FnOnce(&Connection) -> Result<()>) -> Result<()> {
+        self.known.borrow_mut().take();
+        let write = || -> Result<()> {
+            let connection = self.connect()?;
+            connection.execute("BEGIN IMMEDIATE")?;
+            change(&connection)?;
+            connection.execute("COMMIT")
+        };
+        write().with_context(|| format!("Could not save your to-dos ({})", self.path.display()))
+    }
+
+    fn read(&self, list: &str) -> Result<TodoList> {
+        // Reading shouldn't leave an empty database behind
+        if !self.path.exists() {
+            return Ok(TodoList::new(Vec::new()));
+        }
+        let connection = self.connect()?;
+        let mut next_id = 0;
+        connection
+            .prepare("SELECT next_id FROM lists WHERE name = ?")?
+            .query(&[Param::Text(list)], |row| {
+                next_id = u32::try_from(row.int(0)).unwrap_or(0);
+                Ok(())
+            })?;
+        let mut todos = Vec::new();
+        connection
+            .prepare("SELECT todo FROM todos WHERE list = ? ORDER BY rowid")?
+            .query(&[Param::Text(list)], |row| {
+                let todo = serde_json::from_str(&row.text(0))
+                    .context("A to-do in the database could not be read")?;
+                todos.push(todo);
+                Ok(())
+            })?;
+        let mut list = TodoList { next_id, todos };
+        list.fix_next_id();
+        list.fix_order();
+        Ok(list)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Store for SqliteStore {
+    fn load(&self, list: &str) -> Result<TodoList> {
+        let todos = self
+            .read(list)
+            .with_context(|| format!("Could not load your to-dos ({})", self.path.display()))?;
+        *self.known.borrow_mut() = Some((list.to_string(), todos.clone()));
+        Ok(todos)
+    }
+
+    // Writes only the rows that changed since the list was last saved, all in
+    // one transaction, so either every change lands or none does.
+    fn save(&self, list: &str, todos: &TodoList) -> Result<()> {
+        let save = || -> Result<()> {
+            let known = self.known.borrow_mut().take();
+            let saved = match known {
+                Some((name, saved)) if name == list => saved,
+                _ => self.read(list)?,
+            };
+            let mut todos = todos.clone();
+            todos.fix_uids();
+            let (changed, deleted) = changes(&saved, &todos);
+            if changed.is_empty() && deleted.is_empty() && todos.next_id == saved.next_id {
+                *self.known.borrow_mut() = Some((list.to_string(), todos));
+                return Ok(());
+            }
+
+            let connection = self.connect()?;
+            connection.execute("BEGIN IMMEDIATE")?;
+            let mut upsert = connection.prepare(UPSERT_TODO)?;
+            for todo in changed {
+                upsert.execute(&todo_params(list, todo, &to_json(todo)?))?;
+            }
+            let mut delete = connection.prepare(DELETE_TODO)?;
+            for uid in deleted {
+                delete.execute(&[Param::Text(list), Param::Text(uid)])?;
+            }
+            connection
+                .prepare(SET_NEXT_ID)?
+                .execute(&[Param::Text(list), Param::Int(i64::from(todos.next_id))])?;
+            drop((upsert, delete));
+            connection.execute("COMMIT")?;
+            *self.known.borrow_mut() = Some((list.to_string(), todos));
+            Ok(())
+        };
+        save().with_context(|| format!("Could not save your to-dos ({})", self.path.display()))
+    }
+
+    fn add(&self, list: &str, todo: &Todo) -> Result<()> {
+        let json = to_json(todo)?;
+        self.write_rows(|connection| {
+            connection
+                .prepare(UPSERT_TODO)?
+                .execute(&todo_params(list, todo, &json))?;
+            connection
+                .prepare(RAISE_NEXT_ID)?
+                .execute(&[Param::Text(list), Param::Int(i64::from(todo.id) + 1)])
+        })
+    }
+
+    fn update(&self, list: &str, todo: &Todo) -> Result<()> {
+        let json = to_json(todo)?;
+        self.write_rows(|connection| {
+            connection
+                .prepare(UPSERT_TODO)?
+                .execute(&todo_params(list, todo, &json))
+        })
+    }
+
+    fn remove(&self, list: &str, uid: &str) -> Result<()> {
+        self.write_rows(|connection| {
+            connection
+                .prepare(DELETE_TODO)?
+                .execute(&[Param::Text(list), Param::Text(uid)])
+        })
+    }
+
+    // todos.db -> todos.db.undo.json
+    fn undo_path(&self) -> Option<PathBuf> {
+        Some(with_suffix(&self.path, ".undo.json"))
+    }
+}
+
+// Updating in place keeps a to-do's rowid, and with it its place
+#[cfg(feature = "sqlite")]
+const UPSERT_TODO: &str = "INSERT INTO todos (list, uid, todo) VALUES (?, ?, ?)
+     ON CONFLICT (list, uid) DO UPDATE SET todo = excluded.todo";
+#[cfg(feature = "sqlite")]
+const DELETE_TODO: &str = "DELETE FROM todos WHERE list = ? AND uid = ?";
+#[cfg(feature = "sqlite")]
+const SET_NEXT_ID: &str = "INSERT INTO lists (name, next_id) VALUES (?, ?)
+     ON CONFLICT (name) DO UPDATE SET next_id = excluded.next_id";
+#[cfg(feature = "sqlite")]
+const RAISE_NEXT_ID: &str = "INSERT INTO lists (name, next_id) VALUES (?, ?)
+     ON CONFLICT (name) DO UPDATE SET next_id = max(next_id, excluded.next_id)";
+
+#[cfg(feature = "sqlite")]
+fn to_json(todo: &Todo) -> Result<String> {
+    serde_json::to_string(todo).context("Failed to serialize a to-do")
+}
+
+// The parameters of UPSERT_TODO
+#[cfg(feature = "sqlite")]
+fn todo_params<'a>(list: &'a str, todo: &'a Todo, json: &'a str) -> [Param<'a>; 3] {
+    [Param::Text(list), Param::Text(&todo.uid), Param::Text(json)]
+}
+
+// Whether a to-do file is an SQLite database.
+pub fn is_sqlite(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+        ["db", "sqlite", "sqlite3"]
+            .iter()
+            .any(|s| e.eq_ignore_ascii_case(s))
+    })
+}
+
+// Whether a to-do file is kept as JSON Lines.
+fn is_json_lines(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("jsonl"))
+}
+
+// Whether a file is empty or its last byte is a newline.
+fn ends_with_newline(file: &mut File) -> Result<bool> {
+    let length = file
+        .metadata()
+        .context("Failed to read the todo file")?
+        .len();
+    if length == 0 {
+        return Ok(true);
+    }
+    let mut last = [0u8];
+    file.seek(SeekFrom::Start(length - 1))
+        .and_then(|_| file.read_exact(&mut last))
+        .context("Failed to read the todo file")?;
+    Ok(last[0] == b'\n')
+}
+
+// SQLite files are only supported when built with the `sqlite` feature (off
+// by default, since it needs the system's libsqlite3). Without it an SQLite
+// file name would get JSON written into it, so refuse those outright.
+pub fn check_format(path: &Path) -> Result<()> {
+    if cfg!(feature = "sqlite") || !is_sqlite(path) {
+        return Ok(());
+    }
+    bail!(
+        "{} looks like an SQLite file, but this build of todo has no SQLite support (build it with `cargo build --features sqlite`)",
+        path.display()
+    );
+}
+
+// The store for a to-do file, picked by its extension: JSON Lines for
+// .jsonl, SQLite for .db, .sqlite and .sqlite3, and the usual JSON file (or
+// YAML, see yaml.rs) for anything else.
+pub fn open(path: PathBuf, sync: bool) -> Result<Box<dyn Store>> {
+    check_format(&path)?;
+    #[cfg(feature = "sqlite")]
+    if is_sqlite(&path) {
+        return Ok(Box::new(SqliteStore::new(path, sync)));
+    }
+    Ok(if is_json_lines(&path) {
+        Box::new(JsonLinesStore::new(path, sync))
+    } else {
+        Box::new(JsonFileStore::new(path, sync))
+    })
+}
+
+// Lists kept in memory only, for testing code that works on a Store.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    lists: RefCell<HashMap<String, TodoList>>,
+}
+
+#[cfg(test)]
+impl Store for MemoryStore {
+    fn load(&self, list: &str) -> Result<TodoList> {
+        let lists = self.lists.borrow();
+        Ok(lists
+            .get(list)
+            .cloned()
+            .unwrap_or_else(|| TodoList::new(Vec::new())))
+    }
+
+    fn save(&self, list: &str, todos: &TodoList) -> Result<()> {
+        self.lists
+            .borrow_mut()
+            .insert(list.to_string(), todos.clone());
+        Ok(())
+    }
+
+    fn undo_path(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> TodoList {
+        TodoList::new(vec![
+            Todo::new(1, "Buy milk".to_string()),
+            Todo::new(2, "Call the bank".to_string()),
+        ])
+    }
+
+    #[test]
+    fn changes_finds_new_changed_and_deleted_to_dos() {
+        let before = sample();
+        let mut after = before.clone();
+        after.todos[0].completed = true;
+        after.todos.remove(1);
+        after.todos.push(Todo::new(3, "Water plants".to_string()));
+
+        let (changed, deleted) = changes(&before, &after);
+        let tasks: Vec<&str> = changed.iter().map(|t| t.task.as_str()).collect();
+        assert_eq!(tasks, ["Buy milk", "Water plants"]);
+        assert_eq!(deleted, [before.todos[1].uid.as_str()]);
+    }
+
+    #[test]
+    fn save_changes_leaves_the_store_holding_the_new_list() {
+        let store = MemoryStore::default();
+        let work = TodoList::new(vec![Todo::new(1, "Send the report".to_string())]);
+        store.save("work", &work).unwrap();
+        let mut before = sample();
+        store.save("default", &before).unwrap();
+
+        // One added, one changed, one removed (each saved on its own through
+        // add, update and remove), then several changes at once
+        let steps: [fn(&mut TodoList); 4] = [
+            |list| {
+                list.todos.push(Todo::new(3, "Water plants".to_string()));
+                list.next_id = 4;
+            },
+            |list| list.todos[0].completed = true,
+            |list| {
+                list.todos.remove(1);
+            },
+            |list| {
+                list.todos.clear();
+                list.next_id = 10;
+            },
+        ];
+        for step in steps {
+            let mut after = before.clone();
+            step(&mut after);
+            save_changes(&store, "default", &before, &after).unwrap();
+            assert_eq!(store.load("default").unwrap(), after);
+            before = after;
+        }
+        assert_eq!(store.load("work").unwrap(), work);
+    }
+}
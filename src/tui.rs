@@ -0,0 +1,261 @@
+use anyhow::{Context, Result, bail};
+use std::fs::File;
+use std::io::{IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+
+use std::collections::BTreeSet;
+
+use crate::{Todo, TodoList, emoji, format_todo};
+
+// Puts the terminal into raw mode for as long as it is alive, and switches to
+// the alternate screen so the user's scrollback is left alone. Dropping it puts
+// everything back, even if the TUI bails out with an error.
+struct RawTerminal {
+    saved_mode: String,
+    tty: File,
+}
+
+// Runs `stty` against the controlling terminal.
+fn stty(args: &[&str]) -> Result<String> {
+    let tty = File::open("/dev/tty").context("Failed to open the terminal")?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(tty))
+        .output()
+        .context("Failed to run stty")?;
+    if !output.status.success() {
+        bail!("stty {} failed", args.join(" "));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// The number of rows on the terminal, if there is one and stty can tell us.
+pub fn terminal_rows() -> Option<usize> {
+    stty(&["size"])
+        .ok()
+        .and_then(|size| size.split_whitespace().next()?.parse().ok())
+}
+
+impl RawTerminal {
+    fn enter() -> Result<RawTerminal> {
+        let saved_mode = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        let mut tty = File::options()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .context("Failed to open the terminal")?;
+        // Alternate screen, hidden cursor
+        write!(tty, "\x1b[?1049h\x1b[?25l")?;
+        Ok(RawTerminal { saved_mode, tty })
+    }
+
+    // The number of rows on the screen, falling back to 24 if stty can't tell us.
+    fn rows(&self) -> usize {
+        terminal_rows().unwrap_or(24)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        // Show the cursor again and leave the alternate screen
+        let _ = write!(self.tty, "\x1b[?25h\x1b[?1049l");
+        let _ = self.tty.flush();
+        let _ = stty(&[&self.saved_mode]);
+    }
+}
+
+enum Key {
+    Up,
+    Down,
+    Toggle,
+    Delete,
+    Yes,
+    Quit,
+    Other,
+}
+
+fn read_key(tty: &mut File) -> Result<Key> {
+    let mut byte = [0u8; 1];
+    tty.read_exact(&mut byte)?;
+    let key = match byte[0] {
+        b'q' | 3 => Key::Quit, // 3 is Ctrl-C, which raw mode delivers as a byte
+        b'k' => Key::Up,
+        b'j' => Key::Down,
+        b' ' => Key::Toggle,
+        b'd' => Key::Delete,
+        b'y' | b'Y' => Key::Yes,
+        // Arrow keys arrive as ESC [ A (up) and ESC [ B (down)
+        0x1b => match read_escape(tty)?.as_slice() {
+            [b'[', b'A'] => Key::Up,
+            [b'[', b'B'] => Key::Down,
+            _ => Key::Other,
+        },
+        _ => Key::Other,
+    };
+    Ok(key)
+}
+
+// Reads the (up to) two bytes after an ESC. An arrow key sends them straight
+// away, but Esc on its own sends nothing more, so rather than wait for the
+// next two key presses, reads give up after a tenth of a second for now.
+fn read_escape(tty: &mut File) -> Result<Vec<u8>> {
+    stty(&["min", "0", "time", "1"])?;
+    let mut rest = [0u8; 2];
+    let mut length = 0;
+    let result = loop {
+        match tty.read(&mut rest[length..]) {
+            Ok(0) => break Ok(()),
+            Ok(n) => {
+                length += n;
+                if length == rest.len() {
+                    break Ok(());
+                }
+            }
+            Err(e) => break Err(e),
+        }
+    };
+    // Back to waiting for every key
+    stty(&["min", "1", "time", "0"])?;
+    result?;
+    Ok(rest[..length].to_vec())
+}
+
+// Draws the list, with `message` (if any) on the line under the title.
+fn render(
+    tty: &mut File,
+    todos: &[Todo],
+    selected: usize,
+    top: usize,
+    rows: usize,
+    message: Option<&str>,
+) -> Result<()> {
+    // Raw mode doesn't translate \n, so every line ends in \r\n
+    let mut screen = String::from("\x1b[H\x1b[2J");
+    screen.push_str(&format!(
+        "--- Your To-Do List ---  ({} move, space toggle, d delete, q quit)\r\n",
+        emoji("↑/↓", "up/down")
+    ));
+    if let Some(message) = message {
+        screen.push_str(&format!("{}\r\n", message));
+    }
+    if todos.is_empty() {
+        screen.push_str("No to-dos yet! Add one with the 'add' command.\r\n");
+    }
+    let visible = rows
+        .saturating_sub(1 + usize::from(message.is_some()))
+        .max(1);
+    for (index, todo) in todos.iter().enumerate().skip(top).take(visible) {
+        // Notes and multi-line tasks are shown on a single line here
+        let line = format_todo(todo).replace('\n', " ");
+        if index == selected {
+            screen.push_str(&format!("\x1b[7m{}\x1b[0m\r\n", line));
+        } else {
+            screen.push_str(&format!("{}\r\n", line));
+        }
+    }
+    tty.write_all(screen.as_bytes())?;
+    tty.flush()?;
+    Ok(())
+}
+
+// Runs the full-screen to-do browser. Returns true if anything was changed,
+// so the caller knows whether the list needs saving.
+pub fn run(list: &mut TodoList) -> Result<bool> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        bail!("The TUI needs an interactive terminal");
+    }
+
+    let mut terminal = RawTerminal::enter()?;
+    let mut selected = 0;
+    let mut top = 0;
+    let mut changed = false;
+    // Shown until the next key press, e.g. why a to-do can't be completed yet
+    let mut message: Option<String> = None;
+    // Set after `d`, while the question on the message line waits for a y/n
+    let mut deleting = false;
+
+    loop {
+        let rows = terminal.rows();
+        let visible = rows
+            .saturating_sub(1 + usize::from(message.is_some()))
+            .max(1);
+        // Keep the selected line on screen
+        if selected < top {
+            top = selected;
+        } else if selected >= top + visible {
+            top = selected + 1 - visible;
+        }
+        render(
+            &mut terminal.tty,
+            &list.todos,
+            selected,
+            top,
+            rows,
+            message.as_deref(),
+        )?;
+
+        let key = read_key(&mut terminal.tty)?;
+        message = None;
+        if deleting {
+            // Only `y` deletes, as with the prompt of the `delete` command
+            deleting = false;
+            if let Key::Yes = key
+                && selected < list.todos.len()
+            {
+                let todo = list.todos.remove(selected);
+                message = Some(format!("Deleted to-do {}.", todo.id));
+                selected = selected.min(list.todos.len().saturating_sub(1));
+                changed = true;
+            } else {
+                message = Some("Nothing was deleted.".to_string());
+            }
+            continue;
+        }
+        match key {
+            Key::Quit => break,
+            Key::Up => selected = selected.saturating_sub(1),
+            Key::Down => {
+                if selected + 1 < list.todos.len() {
+                    selected += 1;
+                }
+            }
+            Key::Toggle => {
+                // Held up by pending dependencies, as with `complete`
+                let blocked = list
+                    .todos
+                    .get(selected)
+                    .filter(|todo| !todo.completed)
+                    .and_then(|todo| list.blocked_reason(todo, &BTreeSet::new()));
+                if let Some(reason) = blocked {
+                    message = Some(format!("Can't complete it yet: {}.", reason));
+                } else if let Some(completed) = list.todos.get(selected).map(|t| t.completed) {
+                    if completed {
+                        // Takes back a next occurrence as well, like `uncomplete`
+                        if let Some(next) = list.reopen(selected) {
+                            message =
+                                Some(format!("Removed its next occurrence, to-do {}.", next.id));
+                            selected = selected.min(list.todos.len().saturating_sub(1));
+                        }
+                    } else if let Some(next) = list.complete(selected) {
+                        // A recurring to-do comes back, as with `complete`
+                        message = Some(format!("Added its next occurrence, to-do {}.", next.id));
+                    }
+                    changed = true;
+                }
+            }
+            Key::Delete => {
+                if let Some(todo) = list.todos.get(selected) {
+                    message = Some(format!(
+                        "Delete to-do {}: \"{}\"? [y/N]",
+                        todo.id,
+                        todo.task.replace('\n', " ")
+                    ));
+                    deleting = true;
+                }
+            }
+            Key::Yes | Key::Other => {}
+        }
+    }
+    Ok(changed)
+}
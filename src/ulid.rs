@@ -0,0 +1,45 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Crockford's base 32, which leaves out I, L, O and U so IDs are easy to read out.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+// Makes a new ULID (https://github.com/ulid/spec): 48 bits of milliseconds
+// since the Unix epoch followed by 80 random bits, written as 26 characters.
+// ULIDs made on different machines practically never collide, and sorting
+// them as text puts them in the order they were made.
+pub fn generate() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+
+    // std has no random number generator, but every RandomState is seeded
+    // randomly, so hashing with a fresh one gives unpredictable bits. The
+    // counter keeps IDs made within the same millisecond apart.
+    let mut random = 0u128;
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(random);
+        random = (random << 64) | u128::from(hasher.finish());
+    }
+
+    let value = (u128::from(millis & 0xFFFF_FFFF_FFFF) << 80) | (random & ((1 << 80) - 1));
+    (0..26)
+        .rev()
+        .map(|i| ALPHABET[((value >> (i * 5)) & 0x1F) as usize] as char)
+        .collect()
+}
+
+// Whether `text` has the shape of a ULID, ignoring case.
+pub fn is_ulid(text: &str) -> bool {
+    text.len() == 26
+        && text
+            .bytes()
+            .all(|b| ALPHABET.contains(&b.to_ascii_uppercase()))
+}
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::date::Timestamp;
+use crate::{Todo, TodoList, with_suffix};
+
+// How many changes `undo` can step back through.
+const UNDO_LIMIT: usize = 10;
+
+// One change to a list: what it did, and the command that made it, so `undo`
+// can put the list back and say what it reversed. Only the to-dos the change
+// touched are kept, so adding one to a long list doesn't copy all the others.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UndoEntry {
+    pub list: String,
+    pub command: String,
+    pub at: Timestamp,
+    // The to-dos the change altered or removed, as they were before it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    replaced: Vec<Todo>,
+    // ULIDs of the to-dos the change added
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    added: Vec<String>,
+    #[serde(default)]
+    next_id: u32,
+    // The whole list as it was, for changes to to-dos that have no ULID yet
+    // (which can't be told apart), and in histories written by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    before: Option<TodoList>,
+}
+
+impl UndoEntry {
+    // The entry for a change that turned `before` into `after`.
+    pub fn new(list: String, command: String, before: &TodoList, after: &TodoList) -> UndoEntry {
+        let mut entry = UndoEntry {
+            list,
+            command,
+            at: Timestamp::now(),
+            replaced: Vec::new(),
+            added: Vec::new(),
+            next_id: before.next_id,
+            before: None,
+        };
+        if before
+            .todos
+            .iter()
+            .chain(&after.todos)
+            .any(|t| t.uid.is_empty())
+        {
+            entry.before = Some(before.clone());
+            return entry;
+        }
+        let after_by_uid: HashMap<&str, &Todo> =
+            after.todos.iter().map(|t| (t.uid.as_str(), t)).collect();
+        let before_uids: HashSet<&str> = before.todos.iter().map(|t| t.uid.as_str()).collect();
+        entry.replaced = before
+            .todos
+            .iter()
+            .filter(|t| after_by_uid.get(t.uid.as_str()) != Some(t))
+            .cloned()
+            .collect();
+        entry.added = after
+            .todos
+            .iter()
+            .filter(|t| !before_uids.contains(t.uid.as_str()))
+            .map(|t| t.uid.clone())
+            .collect();
+        entry
+    }
+
+    // Reverses the change in `list`, the list as it is now.
+    pub fn undo(&self, mut list: TodoList) -> TodoList {
+        if let Some(before) = &self.before {
+            return before.clone();
+        }
+        let replaced: HashSet<&str> = self.replaced.iter().map(|t| t.uid.as_str()).collect();
+        list.todos
+            .retain(|t| !replaced.contains(t.uid.as_str()) && !self.added.contains(&t.uid));
+        list.todos.extend(self.replaced.iter().cloned());
+        list.next_id = self.next_id;
+        list.fix_next_id();
+        list.fix_order();
+        list
+    }
+}
+
+// Reads the undo log, oldest change first. A missing or damaged log just
+// means there's nothing to undo.
+fn load(path: &Path) -> Vec<UndoEntry> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        eprintln!(
+            "Warning: ignoring the undo history in {}, it could not be read ({}).",
+            path.display(),
+            e
+        );
+        Vec::new()
+    })
+}
+
+// Writes the log through a temporary file and a rename, like the to-do file,
+// so a crash halfway through can't leave a damaged history behind.
+fn save(path: &Path, entries: &[UndoEntry]) -> Result<()> {
+    let json_string =
+        serde_json::to_string_pretty(entries).context("Failed to serialize the undo history")?;
+    let tmp_path = with_suffix(path, ".tmp");
+    File::create(&tmp_path)
+        .and_then(|mut file| file.write_all(json_string.as_bytes()))
+        .and_then(|()| std::fs::rename(&tmp_path, path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// Adds a change to the end of the log, dropping the oldest ones past UNDO_LIMIT.
+pub fn record(path: &Path, entry: UndoEntry) -> Result<()> {
+    let mut entries = load(path);
+    entries.push(entry);
+    if entries.len() > UNDO_LIMIT {
+        entries.drain(..entries.len() - UNDO_LIMIT);
+    }
+    save(path, &entries)
+}
+
+// The most recent change to the named list, if there is one.
+pub fn last(path: &Path, list: &str) -> Option<UndoEntry> {
+    load(path).into_iter().rfind(|e| e.list == list)
+}
+
+// Drops the most recent change to the named list from the log, once it
+// has been undone.
+pub fn remove_last(path: &Path, list: &str) -> Result<()> {
+    let mut entries = load(path);
+    if let Some(index) = entries.iter().rposition(|e| e.list == list) {
+        entries.remove(index);
+        save(path, &entries)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_entry_keeps_only_what_changed_and_undoes_it() {
+        let before = TodoList::new(vec![
+            Todo::new(1, "Buy milk".to_string()),
+            Todo::new(2, "Call the bank".to_string()),
+            Todo::new(3, "Water plants".to_string()),
+        ]);
+        let mut after = before.clone();
+        after.todos[0].completed = true;
+        after.todos.remove(1);
+        after
+            .todos
+            .push(Todo::new(4, "Send the report".to_string()));
+        after.next_id = 5;
+
+        let entry = UndoEntry::new("default".to_string(), "x".to_string(), &before, &after);
+        let tasks: Vec<&str> = entry.replaced.iter().map(|t| t.task.as_str()).collect();
+        assert_eq!(tasks, ["Buy milk", "Call the bank"]);
+        assert_eq!(entry.added, [after.todos[2].uid.clone()]);
+        assert_eq!(entry.undo(after), before);
+    }
+
+    #[test]
+    fn entries_from_older_versions_still_undo() {
+        let json = r#"{"list": "default", "command": "add x", "at": "2025-04-15T10:00:00Z",
+            "before": {"next_id": 2, "todos": [{"id": 1, "task": "Buy milk", "completed": false}]}}"#;
+        let entry: UndoEntry = serde_json::from_str(json).unwrap();
+        let list = entry.undo(TodoList::new(Vec::new()));
+        assert_eq!(list.todos[0].task, "Buy milk");
+    }
+}
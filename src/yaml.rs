@@ -0,0 +1,636 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow, bail};
+use serde::Serialize;
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Number, Value};
+
+// Reading and writing the YAML that to-do files need, without a YAML crate.
+// To-do files are nested maps and lists of plain values, so this covers
+// block-style YAML: `key: value` maps, `- item` lists, strings that are
+// plain, 'single-quoted' or "double-quoted", `|` blocks for text over several
+// lines, `[a, b]` lists of plain values, and `#` comments. Anchors, tags and
+// flow-style maps are not supported and give an error rather than a guess.
+
+// Whether `path` names a YAML file, going by its extension.
+pub fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"))
+}
+
+// A value on its way to YAML. serde_json::Value keeps map keys sorted, which
+// would write a to-do's fields in alphabetical order; this keeps them in the
+// order the struct declares them, so `id` and `task` come first.
+enum Node {
+    Scalar(Value),
+    List(Vec<Node>),
+    Map(Vec<(String, Node)>),
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Node, D::Error> {
+        struct NodeVisitor;
+
+        impl<'de> Visitor<'de> for NodeVisitor {
+            type Value = Node;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("any JSON value")
+            }
+
+            fn visit_unit<E>(self) -> Result<Node, E> {
+                Ok(Node::Scalar(Value::Null))
+            }
+
+            fn visit_bool<E>(self, b: bool) -> Result<Node, E> {
+                Ok(Node::Scalar(Value::Bool(b)))
+            }
+
+            fn visit_i64<E>(self, n: i64) -> Result<Node, E> {
+                Ok(Node::Scalar(n.into()))
+            }
+
+            fn visit_u64<E>(self, n: u64) -> Result<Node, E> {
+                Ok(Node::Scalar(n.into()))
+            }
+
+            fn visit_f64<E>(self, n: f64) -> Result<Node, E> {
+                Ok(Node::Scalar(n.into()))
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Node, E> {
+                Ok(Node::Scalar(s.into()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(Node::List(items))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Node::Map(entries))
+            }
+        }
+
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+// Writes `value` as a YAML document.
+pub fn to_string<T: Serialize>(value: &T) -> Result<String> {
+    let json = serde_json::to_string(value)?;
+    let node: Node = serde_json::from_str(&json)?;
+    let mut out = String::new();
+    match &node {
+        Node::Map(entries) if !entries.is_empty() => write_map(&mut out, entries, 0),
+        Node::List(items) if !items.is_empty() => write_list(&mut out, items, 0),
+        _ => {
+            out.push_str(&scalar(&node));
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+fn write_map(out: &mut String, entries: &[(String, Node)], indent: usize) {
+    for (key, value) in entries {
+        out.push_str(&" ".repeat(indent));
+        out.push_str(&string(key));
+        out.push(':');
+        write_value(out, value, indent);
+    }
+}
+
+fn write_list(out: &mut String, items: &[Node], indent: usize) {
+    for item in items {
+        out.push_str(&" ".repeat(indent));
+        out.push('-');
+        match item {
+            // The first key goes on the dash's line, the rest line up under it
+            Node::Map(entries) if !entries.is_empty() => {
+                let (key, value) = &entries[0];
+                out.push(' ');
+                out.push_str(&string(key));
+                out.push(':');
+                write_value(out, value, indent + 2);
+                write_map(out, &entries[1..], indent + 2);
+            }
+            _ => write_value(out, item, indent),
+        }
+    }
+}
+
+// Writes what follows a `key:` or `-`: a scalar on the same line, or a
+// nested map or list on the lines below.
+fn write_value(out: &mut String, value: &Node, indent: usize) {
+    match value {
+        Node::Map(entries) if !entries.is_empty() => {
+            out.push('\n');
+            write_map(out, entries, indent + 2);
+        }
+        Node::List(items) if !items.is_empty() => {
+            out.push('\n');
+            write_list(out, items, indent + 2);
+        }
+        _ => {
+            out.push(' ');
+            out.push_str(&scalar(value));
+            out.push('\n');
+        }
+    }
+}
+
+fn scalar(value: &Node) -> String {
+    match value {
+        Node::Scalar(Value::String(s)) => string(s),
+        Node::Scalar(value) => value.to_string(),
+        Node::List(_) => "[]".to_string(),
+        Node::Map(_) => "{}".to_string(),
+    }
+}
+
+// A string, left plain when it would read back as the same string and
+// double-quoted otherwise. JSON's string escapes are valid YAML too.
+fn string(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.trim() == s
+        && !s.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.ends_with(':')
+        && !s.chars().any(char::is_control)
+        && matches!(plain_scalar(s), Value::String(_));
+    if plain {
+        s.to_string()
+    } else {
+        Value::String(s.to_string()).to_string()
+    }
+}
+
+// One line of the document, with its indentation measured.
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+impl Line<'_> {
+    // Blank lines and comments carry no structure (except inside `|` blocks)
+    fn is_empty(&self) -> bool {
+        self.text.is_empty() || self.text.starts_with('#')
+    }
+}
+
+struct Parser<'a> {
+    lines: Vec<Line<'a>>,
+    pos: usize,
+}
+
+// Parses a YAML document made of the pieces `to_string` writes (see the top
+// of this file). Errors give the line they were found on.
+pub fn from_str(text: &str) -> Result<Value> {
+    let mut lines = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let content = raw.trim_start_matches(' ');
+        if content.starts_with('\t') {
+            bail!("line {}: indent with spaces, not tabs", index + 1);
+        }
+        lines.push(Line {
+            number: index + 1,
+            indent: raw.len() - content.len(),
+            text: content.trim_end(),
+        });
+    }
+    let mut parser = Parser { lines, pos: 0 };
+    parser.skip_empty();
+    if parser.peek().is_some_and(|line| line.text == "---") {
+        parser.pos += 1;
+        parser.skip_empty();
+    }
+    let Some(first) = parser.peek() else {
+        return Ok(Value::Null);
+    };
+    let value = parser.node(first.indent)?;
+    parser.skip_empty();
+    if let Some(line) = parser.peek() {
+        bail!("line {}: unexpected indentation", line.number);
+    }
+    Ok(value)
+}
+
+impl<'a> Parser<'a> {
+    fn skip_empty(&mut self) {
+        while self.lines.get(self.pos).is_some_and(Line::is_empty) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<&Line<'a>> {
+        self.lines.get(self.pos)
+    }
+
+    // A map or list whose entries start at `indent`.
+    fn node(&mut self, indent: usize) -> Result<Value> {
+        let line = &self.lines[self.pos];
+        if is_list_item(line.text) {
+            self.list(indent)
+        } else {
+            self.map(indent)
+        }
+    }
+
+    fn list(&mut self, indent: usize) -> Result<Value> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_empty();
+            let Some(line) = self.peek() else { break };
+            if line.indent != indent || !is_list_item(line.text) {
+                break;
+            }
+            let rest = line.text[1..].trim_start();
+            if rest.is_empty() {
+                self.pos += 1;
+                items.push(self.nested(indent)?);
+            } else if split_key(rest).is_some() {
+                // "- key: value" starts a map, whose other keys line up with this one
+                let offset = line.text.len() - rest.len();
+                let line = &mut self.lines[self.pos];
+                line.indent += offset;
+                line.text = rest;
+                items.push(self.map(indent + offset)?);
+            } else {
+                let number = line.number;
+                self.pos += 1;
+                items.push(self.value(rest, number, indent)?);
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn map(&mut self, indent: usize) -> Result<Value> {
+        let mut map = Map::new();
+        loop {
+            self.skip_empty();
+            let Some(line) = self.peek() else { break };
+            if line.indent < indent || (line.indent == indent && is_list_item(line.text)) {
+                break;
+            }
+            let number = line.number;
+            if line.indent > indent {
+                bail!("line {}: unexpected indentation", number);
+            }
+            let Some((key, rest)) = split_key(line.text) else {
+                bail!(
+                    "line {}: expected `key: value`, got `{}`",
+                    number,
+                    line.text
+                );
+            };
+            let key = key_string(key, number)?;
+            self.pos += 1;
+            let value = if strip_comment(rest).is_empty() {
+                // A list may sit at the same indentation as its key
+                self.skip_empty();
+                match self.peek() {
+                    Some(next) if next.indent == indent && is_list_item(next.text) => {
+                        self.list(indent)?
+                    }
+                    _ => self.nested(indent)?,
+                }
+            } else {
+                self.value(rest, number, indent)?
+            };
+            if map.insert(key.clone(), value).is_some() {
+                bail!("line {}: `{}` is given twice", number, key);
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    // Whatever is indented further than `indent` on the following lines,
+    // or null if nothing is.
+    fn nested(&mut self, indent: usize) -> Result<Value> {
+        self.skip_empty();
+        match self.peek() {
+            Some(next) if next.indent > indent => self.node(next.indent),
+            _ => Ok(Value::Null),
+        }
+    }
+
+    // The value written after `key:` or `-` on line `number`.
+    fn value(&mut self, text: &str, number: usize, indent: usize) -> Result<Value> {
+        let text = strip_comment(text);
+        let at = |e: anyhow::Error| anyhow!("line {}: {}", number, e);
+        match text {
+            "|" | "|-" | "|+" => Ok(Value::String(self.block(indent, text))),
+            "[]" => Ok(Value::Array(Vec::new())),
+            "{}" => Ok(Value::Object(Map::new())),
+            _ if text.starts_with('[') => flow_list(text).map_err(at),
+            _ if text.starts_with('{') => {
+                bail!("line {}: write maps one `key: value` per line", number)
+            }
+            _ if text.starts_with(['&', '*', '!']) => {
+                bail!(
+                    "line {}: anchors, aliases and tags aren't supported",
+                    number
+                )
+            }
+            _ => quoted_or_plain(text).map_err(at),
+        }
+    }
+
+    // The lines of a `|` block: everything indented further than `indent`,
+    // kept exactly, minus the block's own indentation.
+    fn block(&mut self, indent: usize, header: &str) -> String {
+        let start = self.pos;
+        while let Some(line) = self.peek() {
+            if !line.text.is_empty() && line.indent <= indent {
+                break;
+            }
+            self.pos += 1;
+        }
+        let lines = &self.lines[start..self.pos];
+        let margin = lines
+            .iter()
+            .filter(|l| !l.text.is_empty())
+            .map(|l| l.indent)
+            .min()
+            .unwrap_or(0);
+        let mut text: String = lines
+            .iter()
+            .map(|l| {
+                let pad = " ".repeat(l.indent.saturating_sub(margin));
+                if l.text.is_empty() {
+                    "\n".to_string()
+                } else {
+                    format!("{}{}\n", pad, l.text)
+                }
+            })
+            .collect();
+        match header {
+            // Keep every trailing line break
+            "|+" => {}
+            // Keep none
+            "|-" => text.truncate(text.trim_end_matches('\n').len()),
+            // Keep exactly one
+            _ => {
+                text.truncate(text.trim_end_matches('\n').len());
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+            }
+        }
+        text
+    }
+}
+
+fn is_list_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+// Splits "key: value" (or "key:" alone) at the colon, looking past any
+// quotes around the key. None if the line isn't a map entry.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let end = match text.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let close = closing_quote(text, quote)?;
+            let after = &text[close + 1..];
+            return after
+                .strip_prefix(':')
+                .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+                .map(|rest| (&text[..=close], rest.trim_start()));
+        }
+        _ => text
+            .find(": ")
+            .or_else(|| text.ends_with(':').then(|| text.len() - 1))?,
+    };
+    let key = &text[..end];
+    if key.contains(" #") {
+        return None;
+    }
+    Some((key.trim_end(), text[end + 1..].trim_start()))
+}
+
+fn key_string(key: &str, number: usize) -> Result<String> {
+    match quoted_or_plain(key).map_err(|e| anyhow!("line {}: {}", number, e))? {
+        Value::String(s) => Ok(s),
+        // `1: x` or `true: x` are keys too, just not written as strings
+        other => Ok(other.to_string()),
+    }
+}
+
+// Drops a trailing `# comment` that isn't inside quotes.
+fn strip_comment(text: &str) -> &str {
+    if text.starts_with('#') {
+        return "";
+    }
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && previous == ' ' => return text[..i].trim_end(),
+            None => {}
+        }
+        previous = c;
+    }
+    text
+}
+
+// Where the quote opening `text` is closed: the next unescaped " for double
+// quotes, the next ' that isn't doubled for single quotes.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if quote == '"' => i += 2,
+            b'\'' if quote == '\'' && bytes.get(i + 1) == Some(&b'\'') => i += 2,
+            b if b == quote as u8 => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn quoted_or_plain(text: &str) -> Result<Value> {
+    match text.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let close = closing_quote(text, quote)
+                .ok_or_else(|| anyhow!("missing closing {} in {}", quote, text))?;
+            if close + 1 != text.len() {
+                bail!(
+                    "unexpected `{}` after the closing quote",
+                    text[close + 1..].trim_start()
+                );
+            }
+            let inner = &text[1..close];
+            if quote == '\'' {
+                Ok(Value::String(inner.replace("''", "'")))
+            } else {
+                serde_json::from_str(text).map_err(|e| anyhow!("invalid string {} ({})", text, e))
+            }
+        }
+        _ => Ok(plain_scalar(text)),
+    }
+}
+
+// A value written without quotes: null, a boolean, a number or a string.
+fn plain_scalar(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        _ => {}
+    }
+    if let Ok(n) = text.parse::<i64>() {
+        return Value::Number(n.into());
+    }
+    if let Ok(n) = text.parse::<u64>() {
+        return Value::Number(n.into());
+    }
+    if text.contains(|c: char| c.is_ascii_digit())
+        && !text.contains(|c: char| c.is_alphabetic() && c != 'e' && c != 'E')
+        && let Some(n) = text.parse::<f64>().ok().and_then(Number::from_f64)
+    {
+        return Value::Number(n);
+    }
+    Value::String(text.to_string())
+}
+
+// `[a, "b, c", 3]`: a list of plain or quoted values on one line.
+fn flow_list(text: &str) -> Result<Value> {
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .ok_or_else(|| anyhow!("missing closing ] in {}", text))?;
+    let mut items = Vec::new();
+    let mut rest = inner.trim();
+    while !rest.is_empty() {
+        let end = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let close = closing_quote(rest, quote)
+                    .ok_or_else(|| anyhow!("missing closing {} in {}", quote, text))?;
+                close + 1
+            }
+            _ => rest.find(',').unwrap_or(rest.len()),
+        };
+        let item = rest[..end].trim();
+        if item.starts_with(['[', '{']) {
+            bail!("nested lists and maps go on lines of their own, not inside [...]");
+        }
+        items.push(quoted_or_plain(item)?);
+        rest = rest[end..].trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+        } else if !rest.is_empty() {
+            bail!("expected `,` between the items of {}", text);
+        }
+    }
+    Ok(Value::Array(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trips_a_to_do_file() {
+        let file = json!({
+            "version": 1,
+            "lists": {
+                "default": {
+                    "next_id": 3,
+                    "todos": [
+                        {
+                            "id": 1,
+                            "task": "Buy milk: oat, not soy #important",
+                            "completed": false,
+                            "tags": ["home", "errands"],
+                            "note": "Line one\n  indented\n",
+                        },
+                        {"id": 2, "task": "true", "completed": true, "depends_on": [1]},
+                        {"id": 3, "task": "", "completed": false, "tags": []},
+                    ],
+                },
+                "work stuff": {"next_id": 1, "todos": []},
+            },
+        });
+        let text = to_string(&file).unwrap();
+        assert_eq!(from_str(&text).unwrap(), file, "{}", text);
+    }
+
+    #[test]
+    fn writes_plain_strings_where_it_can() {
+        let text =
+            to_string(&json!({"task": "Buy milk", "due": "2025-04-15", "id": "42"})).unwrap();
+        assert!(text.contains("task: Buy milk\n"));
+        assert!(text.contains("due: 2025-04-15\n"));
+        // Would read back as a number
+        assert!(text.contains("id: \"42\"\n"));
+    }
+
+    #[test]
+    fn reads_hand_written_yaml() {
+        let text = "\
+---
+# My lists
+lists:
+  default:
+    next_id: 2
+    todos:
+    - id: 1
+      task: 'It''s done'   # a comment
+      tags: [home, \"a, b\"]
+      note: |
+        First line
+
+        Third line
+      completed: yes-ish
+";
+        let value = from_str(text).unwrap();
+        let todo = &value["lists"]["default"]["todos"][0];
+        assert_eq!(todo["task"], "It's done");
+        assert_eq!(todo["tags"], json!(["home", "a, b"]));
+        assert_eq!(todo["note"], "First line\n\nThird line\n");
+        assert_eq!(todo["completed"], "yes-ish");
+        assert_eq!(value["lists"]["default"]["next_id"], 2);
+    }
+
+    #[test]
+    fn block_chomping() {
+        let keep = from_str("a: |+\n  x\n\nb: 1\n").unwrap();
+        assert_eq!(keep["a"], "x\n\n");
+        let strip = from_str("a: |-\n  x\n\nb: 1\n").unwrap();
+        assert_eq!(strip["a"], "x");
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        for (text, message) in [
+            ("a: 1\n\tb: 2\n", "line 2: indent with spaces"),
+            ("a: 1\n   b: 2\n", "line 2: unexpected indentation"),
+            ("a: 1\njust text\n", "line 2: expected `key: value`"),
+            ("a: 1\na: 2\n", "line 2: `a` is given twice"),
+            ("a: {b: 1}\n", "line 1: write maps one"),
+            ("a: *ref\n", "line 1: anchors"),
+            ("a: \"open\n", "line 1: missing closing \""),
+            ("a: [1, 2\n", "line 1: missing closing ]"),
+            ("a: \"x\" y\n", "line 1: unexpected `y`"),
+        ] {
+            let error = from_str(text).unwrap_err().to_string();
+            assert!(error.starts_with(message), "{:?}: {}", text, error);
+        }
+    }
+}
@@ -0,0 +1,775 @@
+// End-to-end tests: each one runs the real binary against a to-do file in a
+// fresh temporary directory, so a real todos.json is never touched.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// A temporary directory that is removed again when the test is done.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> TempDir {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "todo-cli-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    fn todo_file(&self) -> PathBuf {
+        self.0.join("todos.json")
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Runs the binary with `--file` pointing into `dir`. The config directory is
+// moved into `dir` too, so the user's own config.toml can't change the results.
+fn todo(dir: &TempDir, args: &[&str]) -> Output {
+    todo_with_file(dir, &dir.todo_file(), args)
+}
+
+// Like `todo`, with another to-do file in `dir`.
+fn todo_with_file(dir: &TempDir, file: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust_todo_cli"))
+        .arg("--file")
+        .arg(file)
+        .args(args)
+        .env("XDG_CONFIG_HOME", &dir.0)
+        .env_remove("TODO_FILE")
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// The default list's to-dos, as stored in the file.
+fn stored_todos(path: &Path) -> Vec<serde_json::Value> {
+    let contents = std::fs::read_to_string(path).unwrap();
+    let file: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    file["lists"]["default"]["todos"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[test]
+fn add_saves_the_task() {
+    let dir = TempDir::new();
+    let output = todo(&dir, &["add", "Buy milk"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Added new to-do: \"Buy milk\" (ID: 1)"));
+
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0]["id"], 1);
+    assert_eq!(todos[0]["task"], "Buy milk");
+    assert_eq!(todos[0]["completed"], false);
+    assert_eq!(todos[0]["priority"], "medium");
+}
+
+#[test]
+fn add_with_options_and_inline_details() {
+    let dir = TempDir::new();
+    let output = todo(
+        &dir,
+        &[
+            "add",
+            "File taxes @money",
+            "--due",
+            "2025-04-15",
+            "--tag",
+            "home",
+        ],
+    );
+    assert!(output.status.success());
+    let output = todo(&dir, &["add", "Pay rent !high"]);
+    assert!(output.status.success());
+
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos[0]["task"], "File taxes");
+    assert_eq!(todos[0]["due"], "2025-04-15");
+    assert_eq!(todos[0]["tags"], serde_json::json!(["home", "money"]));
+    assert_eq!(todos[1]["task"], "Pay rent");
+    assert_eq!(todos[1]["priority"], "high");
+}
+
+#[test]
+fn inline_details_leave_the_rest_of_the_text_as_typed() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy  milk   twice"]);
+    todo(&dir, &["add", "@home Call  mum @family  !high"]);
+    todo(&dir, &["add", "Pack:\n  - socks @trip\n  - charger"]);
+
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos[0]["task"], "Buy  milk   twice");
+    assert_eq!(todos[1]["task"], "Call  mum");
+    assert_eq!(todos[1]["tags"], serde_json::json!(["home", "family"]));
+    assert_eq!(todos[2]["task"], "Pack:\n  - socks\n  - charger");
+}
+
+#[test]
+fn add_unique_refuses_duplicates_with_a_failing_status() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk"]);
+    let output = todo(&dir, &["add", "--unique", "buy milk", "Call the bank"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("already on the list as to-do 1"));
+    assert_eq!(stored_todos(&dir.todo_file()).len(), 2);
+}
+
+#[test]
+fn add_rejects_an_empty_task() {
+    let dir = TempDir::new();
+    let output = todo(&dir, &["add", "   "]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Error: The task description can't be empty"));
+    assert!(!dir.todo_file().exists());
+}
+
+#[test]
+fn add_print_id_prints_only_the_id() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "First"]);
+    let output = todo(&dir, &["add", "Second", "--print-id"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "2\n");
+}
+
+#[test]
+fn list_shows_the_tasks() {
+    let dir = TempDir::new();
+    let output = todo(&dir, &["list"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("No to-dos yet!"));
+
+    todo(&dir, &["add", "Buy milk", "Call the bank"]);
+    todo(&dir, &["complete", "1"]);
+    let output = todo(&dir, &["list", "--plain"]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "--- Your To-Do List ---\n[x] 1: Buy milk\n[ ] 2: Call the bank\n"
+    );
+
+    let output = todo(&dir, &["list"]);
+    assert!(stdout(&output).contains("1/2 completed (50%)"));
+}
+
+#[test]
+fn list_reverse_flips_every_sort() {
+    let dir = TempDir::new();
+    todo(
+        &dir,
+        &["add", "Low", "--priority", "low", "--due", "2025-03-01"],
+    );
+    todo(&dir, &["add", "High", "--priority", "high"]);
+    todo(&dir, &["add", "Medium", "--due", "2025-01-01"]);
+    todo(&dir, &["complete", "2"]);
+
+    let ids = |args: &[&str]| -> Vec<u64> {
+        let output = todo(&dir, &[&["list", "--json", "--reverse"], args].concat());
+        let todos: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let todos = todos.as_array().unwrap();
+        todos.iter().map(|t| t["id"].as_u64().unwrap()).collect()
+    };
+    assert_eq!(ids(&[]), [3, 2, 1]);
+    assert_eq!(ids(&["--sort", "id"]), [3, 2, 1]);
+    assert_eq!(ids(&["--sort", "priority"]), [1, 3, 2]);
+    assert_eq!(ids(&["--sort", "due"]), [2, 1, 3]);
+    assert_eq!(ids(&["--sort", "due", "--status", "pending"]), [1, 3]);
+}
+
+#[test]
+fn list_limit_must_be_at_least_one() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk"]);
+    let output = todo(&dir, &["list", "--limit", "0"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("invalid value '0' for '--limit"));
+}
+
+#[test]
+fn list_json_is_pure_json() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk"]);
+    let output = todo(&dir, &["list", "--json"]);
+    assert!(output.status.success());
+    let todos: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(todos[0]["task"], "Buy milk");
+}
+
+#[test]
+fn complete_marks_the_task_done() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk", "Call the bank"]);
+    let output = todo(&dir, &["complete", "milk"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Completed to-do 1: \"Buy milk\""));
+
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos[0]["completed"], true);
+    assert!(todos[0]["completed_at"].is_string());
+    assert_eq!(todos[1]["completed"], false);
+}
+
+#[test]
+fn index_picks_to_dos_by_their_place_in_the_list() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "First", "Second", "Third"]);
+    todo(&dir, &["pin", "3"]);
+
+    // The pinned to-do is shown first, so position 1 is ID 3
+    let output = todo(&dir, &["complete", "--index", "1"]);
+    assert!(stdout(&output).contains("Completed to-do 3"));
+    let output = todo(&dir, &["delete", "-i", "3", "--yes"]);
+    assert!(stdout(&output).contains("Deleted to-do with ID 2."));
+
+    let output = todo(&dir, &["complete", "-i", "5"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("There is no to-do at position 5"));
+}
+
+#[test]
+fn complete_is_blocked_by_pending_dependencies() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Write report"]);
+    todo(&dir, &["add", "Send report", "--depends-on", "1"]);
+
+    let output = todo(&dir, &["complete", "2"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("to-do 2 is waiting on to-do 1"));
+    assert_eq!(stored_todos(&dir.todo_file())[1]["completed"], false);
+
+    let output = todo(&dir, &["complete", "2", "--force"]);
+    assert!(output.status.success());
+    assert_eq!(stored_todos(&dir.todo_file())[1]["completed"], true);
+}
+
+#[test]
+fn reopening_a_recurring_to_do_takes_back_its_next_occurrence() {
+    let dir = TempDir::new();
+    todo(
+        &dir,
+        &[
+            "add",
+            "Water plants",
+            "--recur",
+            "weekly",
+            "--due",
+            "2025-04-15",
+        ],
+    );
+    todo(&dir, &["complete", "1"]);
+    assert_eq!(stored_todos(&dir.todo_file()).len(), 2);
+
+    for reopen in [
+        &["uncomplete", "1"][..],
+        &["toggle", "1"],
+        &["complete", "--undo-last"],
+    ] {
+        let output = todo(&dir, reopen);
+        assert!(stdout(&output).contains("Removed to-do "), "{:?}", reopen);
+        let todos = stored_todos(&dir.todo_file());
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0]["completed"], false);
+        assert_eq!(todos[0]["recur"], "weekly");
+        todo(&dir, &["complete", "1"]);
+    }
+}
+
+#[test]
+fn toggling_a_recurring_to_do_adds_its_next_occurrence() {
+    let dir = TempDir::new();
+    todo(
+        &dir,
+        &[
+            "add",
+            "Water plants",
+            "--recur",
+            "daily",
+            "--due",
+            "2025-01-01",
+        ],
+    );
+
+    let output = todo(&dir, &["toggle", "1"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("✅ Marked complete to-do 1: \"Water plants\"\n"));
+    assert!(stdout(&output).contains("added as to-do 2 (due 2025-01-02)"));
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos.len(), 2);
+    assert_eq!(todos[0]["completed"], true);
+    assert!(todos[0].get("recur").is_none());
+    assert_eq!(todos[1]["completed"], false);
+    assert_eq!(todos[1]["recur"], "daily");
+    assert_eq!(todos[1]["due"], "2025-01-02");
+
+    // Toggling it back takes the copy away again
+    let output = todo(&dir, &["toggle", "1", "--no-emoji"]);
+    assert!(stdout(&output).starts_with("Marked incomplete to-do 1: \"Water plants\"\n"));
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0]["recur"], "daily");
+}
+
+#[test]
+fn toggle_is_blocked_by_pending_dependencies() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Write report"]);
+    todo(&dir, &["add", "Send report", "--depends-on", "1"]);
+
+    let output = todo(&dir, &["toggle", "2"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("to-do 2 is waiting on to-do 1"));
+    assert_eq!(stored_todos(&dir.todo_file())[1]["completed"], false);
+
+    let output = todo(&dir, &["toggle", "2", "--force"]);
+    assert!(output.status.success());
+    assert_eq!(stored_todos(&dir.todo_file())[1]["completed"], true);
+}
+
+#[test]
+fn delete_removes_the_task() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk", "Call the bank"]);
+    let output = todo(&dir, &["delete", "1", "--yes"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Deleted to-do with ID 1."));
+
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0]["task"], "Call the bank");
+}
+
+#[test]
+fn delete_without_yes_needs_a_terminal() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk"]);
+    let output = todo(&dir, &["delete", "1"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Nothing was deleted."));
+    assert_eq!(stored_todos(&dir.todo_file()).len(), 1);
+}
+
+#[test]
+fn missing_ids_are_errors() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk"]);
+
+    for command in [
+        &["complete", "9"][..],
+        &["delete", "9", "--yes"],
+        &["edit", "9", "--priority", "high"],
+        &["show", "9"],
+        &["snooze", "9", "2d"],
+    ] {
+        let output = todo(&dir, command);
+        assert_eq!(output.status.code(), Some(1), "{:?}", command);
+        assert!(
+            stderr(&output).contains("Error: To-do with ID 9 not found."),
+            "{:?}: {}",
+            command,
+            stderr(&output)
+        );
+    }
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0]["completed"], false);
+}
+
+#[test]
+fn some_missing_ids_still_update_the_others() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk"]);
+    let output = todo(&dir, &["complete", "1", "9"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stored_todos(&dir.todo_file())[0]["completed"], true);
+}
+
+#[test]
+fn edit_changes_only_the_given_fields() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk", "--due", "2025-04-15"]);
+    let output = todo(&dir, &["edit", "1", "--new-task", "Buy oat milk"]);
+    assert!(output.status.success());
+
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos[0]["task"], "Buy oat milk");
+    assert_eq!(todos[0]["due"], "2025-04-15");
+}
+
+#[test]
+fn snooze_pushes_the_due_date_back() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "File taxes", "--due", "2025-01-31"]);
+    let output = todo(&dir, &["snooze", "1", "1m"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("now due 2025-02-28"));
+    assert_eq!(stored_todos(&dir.todo_file())[0]["due"], "2025-02-28");
+}
+
+#[test]
+fn undo_reverses_the_last_change() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk"]);
+    todo(&dir, &["delete", "1", "--yes"]);
+    assert!(stored_todos(&dir.todo_file()).is_empty());
+
+    let output = todo(&dir, &["undo"]);
+    assert!(output.status.success());
+    assert_eq!(stored_todos(&dir.todo_file())[0]["task"], "Buy milk");
+}
+
+#[test]
+fn a_lock_file_left_by_a_killed_command_is_taken_over() {
+    let dir = TempDir::new();
+    let mut lock = dir.todo_file().into_os_string();
+    lock.push(".lock");
+    std::fs::write(&lock, "999999\n").unwrap();
+
+    let output = todo(&dir, &["add", "Buy milk"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!Path::new(&lock).exists());
+}
+
+#[test]
+fn dry_run_leaves_the_file_alone() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk"]);
+    let before = std::fs::read_to_string(dir.todo_file()).unwrap();
+    let output = todo(&dir, &["--dry-run", "complete", "1"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Dry run"));
+    assert_eq!(std::fs::read_to_string(dir.todo_file()).unwrap(), before);
+}
+
+#[test]
+fn changes_that_change_nothing_leave_the_file_alone() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk"]);
+    // Formatted by hand, which a save would undo
+    let contents = std::fs::read_to_string(dir.todo_file()).unwrap();
+    let file: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    let compact = serde_json::to_string(&file).unwrap();
+    std::fs::write(dir.todo_file(), &compact).unwrap();
+
+    let output = todo(&dir, &["rename", "1", "Buy milk"]);
+    assert!(output.status.success());
+    assert_eq!(std::fs::read_to_string(dir.todo_file()).unwrap(), compact);
+
+    // Nor is there a do-nothing step to undo: undo takes back the add
+    todo(&dir, &["undo"]);
+    assert!(stored_todos(&dir.todo_file()).is_empty());
+}
+
+#[test]
+fn legacy_files_are_still_read() {
+    let dir = TempDir::new();
+    std::fs::write(
+        dir.todo_file(),
+        r#"[{"id": 1, "task": "Old task", "completed": false}]"#,
+    )
+    .unwrap();
+    let output = todo(&dir, &["list", "--plain"]);
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("[ ] 1: Old task"));
+
+    // The next save upgrades the file to the current layout
+    todo(&dir, &["add", "New task"]);
+    let contents = std::fs::read_to_string(dir.todo_file()).unwrap();
+    let file: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    assert_eq!(file["version"], 1);
+    assert_eq!(stored_todos(&dir.todo_file()).len(), 2);
+}
+
+#[test]
+fn jsonl_files_are_appended_to() {
+    let dir = TempDir::new();
+    let file = dir.0.join("todos.jsonl");
+    todo_with_file(&dir, &file, &["add", "Buy milk", "Call the bank"]);
+    todo_with_file(&dir, &file, &["complete", "1"]);
+    todo_with_file(&dir, &file, &["delete", "2", "--yes"]);
+
+    let contents = std::fs::read_to_string(&file).unwrap();
+    let records: Vec<serde_json::Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records[0]["todo"]["task"], "Buy milk");
+    assert!(records.last().unwrap()["deleted"].is_string());
+
+    let output = todo_with_file(&dir, &file, &["list", "--plain"]);
+    assert_eq!(
+        stdout(&output),
+        "--- Your To-Do List ---\n[x] 1: Buy milk\n"
+    );
+}
+
+#[test]
+fn yaml_files_are_read_and_written_as_yaml() {
+    let dir = TempDir::new();
+    let file = dir.0.join("todos.yaml");
+    todo_with_file(&dir, &file, &["add", "Buy milk: oat", "--tag", "home"]);
+    todo_with_file(&dir, &file, &["complete", "1"]);
+
+    let contents = std::fs::read_to_string(&file).unwrap();
+    assert!(contents.starts_with("version: 1\nlists:\n"), "{}", contents);
+    assert!(
+        contents.contains("task: \"Buy milk: oat\"\n"),
+        "{}",
+        contents
+    );
+
+    // A hand-written file is read too
+    std::fs::write(
+        &file,
+        "lists:\n  default:\n    todos:\n      - id: 1\n        task: Call the bank  # urgent\n        completed: false\n",
+    )
+    .unwrap();
+    let output = todo_with_file(&dir, &file, &["list", "--plain"]);
+    assert_eq!(
+        stdout(&output),
+        "--- Your To-Do List ---\n[ ] 1: Call the bank\n"
+    );
+}
+
+#[cfg(not(feature = "sqlite"))]
+#[test]
+fn sqlite_files_need_the_sqlite_feature() {
+    let dir = TempDir::new();
+    let file = dir.0.join("todos.db");
+    let output = todo_with_file(&dir, &file, &["add", "Buy milk"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("build it with `cargo build --features sqlite`"));
+    assert!(!file.exists());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_files_keep_a_row_per_to_do() {
+    let dir = TempDir::new();
+    let file = dir.0.join("todos.db");
+    todo_with_file(
+        &dir,
+        &file,
+        &["add", "Buy milk", "Call the bank", "Water plants"],
+    );
+    todo_with_file(&dir, &file, &["complete", "1"]);
+    todo_with_file(&dir, &file, &["delete", "2", "--yes"]);
+
+    let output = todo_with_file(&dir, &file, &["list", "--plain"]);
+    assert_eq!(
+        stdout(&output),
+        "--- Your To-Do List ---\n[x] 1: Buy milk\n[ ] 3: Water plants\n"
+    );
+    todo_with_file(&dir, &file, &["undo"]);
+    let output = todo_with_file(&dir, &file, &["list", "--plain"]);
+    assert!(stdout(&output).contains("2: Call the bank"));
+    assert!(
+        std::fs::read(&file)
+            .unwrap()
+            .starts_with(b"SQLite format 3")
+    );
+}
+
+#[test]
+fn doctor_reports_and_fixes_problems() {
+    let dir = TempDir::new();
+    std::fs::write(
+        dir.todo_file(),
+        r#"[{"id": 1, "task": "Buy milk ", "completed": false},
+            {"id": 1, "task": "Call the bank", "completed": false, "tags": ["Work"]}]"#,
+    )
+    .unwrap();
+    let output = todo(&dir, &["doctor"]);
+    assert!(output.status.success());
+    let report = stdout(&output);
+    assert!(report.contains("2 to-dos share ID 1."));
+    assert!(report.contains("To-do 1's description starts or ends with spaces."));
+
+    let output = todo(&dir, &["doctor", "--fix"]);
+    assert!(output.status.success());
+    let todos = stored_todos(&dir.todo_file());
+    assert_eq!(todos[0]["task"], "Buy milk");
+    assert_eq!(todos[1]["id"], 2);
+    assert_eq!(todos[1]["tags"], serde_json::json!(["work"]));
+    assert_eq!(stdout(&todo(&dir, &["doctor"])), "No problems found.\n");
+}
+
+#[test]
+fn jsonl_and_json_files_keep_separate_undo_histories() {
+    let dir = TempDir::new();
+    let jsonl = dir.0.join("todos.jsonl");
+    todo(&dir, &["add", "JSON item"]);
+    todo_with_file(&dir, &jsonl, &["add", "Lines item"]);
+    todo_with_file(&dir, &jsonl, &["add", "Another lines item"]);
+
+    // Takes back the JSON file's own add, not a change to the .jsonl file
+    todo(&dir, &["undo"]);
+    assert!(stored_todos(&dir.todo_file()).is_empty());
+    let output = todo_with_file(&dir, &jsonl, &["list", "--plain"]);
+    assert!(stdout(&output).contains("Another lines item"));
+}
+
+#[test]
+fn format_switches_the_output_of_read_commands() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk, oat", "Call the bank"]);
+
+    let output = todo(&dir, &["--format", "csv", "list"]);
+    assert_eq!(
+        stdout(&output),
+        "id,task,completed\n1,\"Buy milk, oat\",false\n2,Call the bank,false\n"
+    );
+
+    let output = todo(&dir, &["search", "bank", "--format", "json"]);
+    let matches: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(matches.as_array().unwrap().len(), 1);
+    assert_eq!(matches[0]["id"], 2);
+
+    let output = todo(&dir, &["stats", "--format", "json"]);
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(stats["total"], 2);
+    assert_eq!(stats["pending"], 2);
+}
+
+#[test]
+fn stats_by_tag_counts_each_tag() {
+    let dir = TempDir::new();
+    todo(
+        &dir,
+        &["add", "Write report", "--tag", "work", "--tag", "home"],
+    );
+    todo(&dir, &["add", "Send report", "--tag", "work"]);
+    todo(&dir, &["add", "Buy milk"]);
+    todo(&dir, &["complete", "2"]);
+
+    let output = todo(&dir, &["stats", "--by-tag", "--format", "csv"]);
+    assert_eq!(
+        stdout(&output),
+        "tag,total,completed,pending\nhome,1,0,1\nwork,2,1,1\n(none),1,0,1\n"
+    );
+}
+
+#[test]
+fn log_shows_completed_items_newest_first() {
+    let dir = TempDir::new();
+    std::fs::write(
+        dir.todo_file(),
+        r#"[{"id": 1, "task": "Old", "completed": true, "completed_at": "2020-01-01T10:00:00Z"},
+            {"id": 2, "task": "Pending", "completed": false}]"#,
+    )
+    .unwrap();
+    todo(&dir, &["add", "Recent"]);
+    todo(&dir, &["complete", "3"]);
+
+    let output = todo(&dir, &["log"]);
+    assert!(output.status.success());
+    let log = stdout(&output);
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines[0], "--- Completed ---");
+    assert!(lines[1].ends_with("  3: Recent"));
+    assert_eq!(lines[2], "2020-01-01  1: Old");
+    assert_eq!(lines.len(), 3);
+
+    let output = todo(&dir, &["log", "--days", "7"]);
+    assert!(!stdout(&output).contains("Old"));
+}
+
+#[test]
+fn powershell_completions_offer_subcommands_and_values() {
+    let dir = TempDir::new();
+    let output = todo(&dir, &["completions", "powershell"]);
+    assert!(output.status.success());
+    let script = stdout(&output);
+    assert!(script.starts_with("Register-ArgumentCompleter -Native -CommandName 'rust_todo_cli'"));
+    assert!(script.contains("'add' { $words = @('--due', '-d',"));
+    assert!(script.contains("'--priority' { $words = @('low', 'medium', 'high') }"));
+}
+
+#[test]
+fn since_until_and_log_use_the_local_date() {
+    let dir = TempDir::new();
+    // 05:00 UTC on the 2nd is still the evening of the 1st ten hours west of UTC
+    std::fs::write(
+        dir.todo_file(),
+        r#"[{"id": 1, "task": "Late night", "completed": true,
+             "created_at": "2025-01-02T05:00:00Z", "completed_at": "2025-01-02T05:00:00Z"}]"#,
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rust_todo_cli"))
+            .arg("--file")
+            .arg(dir.todo_file())
+            .args(args)
+            .env("XDG_CONFIG_HOME", &dir.0)
+            .env("TZ", "XYZ+10")
+            .env_remove("TODO_FILE")
+            .output()
+            .unwrap();
+        stdout(&output)
+    };
+
+    let window = [
+        "list",
+        "--status",
+        "all",
+        "--since",
+        "2025-01-01",
+        "--until",
+        "2025-01-01",
+    ];
+    assert!(run(&window).contains("Late night"));
+    assert!(!run(&["list", "--status", "all", "--since", "2025-01-02"]).contains("Late night"));
+    assert!(run(&["log"]).contains("2025-01-01  1: Late night"));
+}
+
+#[test]
+fn renumber_keeps_the_id_counter() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "One", "Two", "Three", "Four"]);
+    todo(&dir, &["delete", "1", "4", "--yes"]);
+
+    let output = todo(&dir, &["renumber"]);
+    assert!(stdout(&output).contains("2 -> 1: \"Two\""));
+    todo(&dir, &["add", "Five"]);
+    let ids: Vec<u64> = stored_todos(&dir.todo_file())
+        .iter()
+        .map(|t| t["id"].as_u64().unwrap())
+        .collect();
+    assert_eq!(ids, [1, 2, 5]);
+}
+
+#[test]
+fn group_by_is_refused_for_machine_formats() {
+    let dir = TempDir::new();
+    todo(&dir, &["add", "Buy milk", "--tag", "home"]);
+
+    for format in ["json", "csv"] {
+        let output = todo(&dir, &["list", "--group-by", "tags", "--format", format]);
+        assert_eq!(output.status.code(), Some(1), "{}", format);
+        assert!(stderr(&output).contains(&format!(
+            "--group-by can't be used with --format {}",
+            format
+        )));
+        assert!(stdout(&output).is_empty());
+    }
+
+    let output = todo(&dir, &["list", "--group-by", "tags", "--format", "table"]);
+    assert!(stdout(&output).contains("home:"));
+}